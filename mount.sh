@@ -1,5 +0,0 @@
-mount --bind /bin/ jail/bin
-mount --bind /lib jail/lib
-mount --bind /usr/lib jail/usr/lib
-mount --bind /usr/lib64 jail/usr/lib64/
-mount --bind /lib64 jail/lib64
\ No newline at end of file
@@ -1,9 +1,12 @@
-use oj_judger::{config, judge::JudgeInfo};
 use nats;
+use oj_judger::{
+    config,
+    judge::{JudgeInfo, JudgeMode},
+};
 use tracing::debug;
 // use tracing::debug;
-use tracing_subscriber::fmt;
 use std::{env, error::Error, io};
+use tracing_subscriber::fmt;
 
 fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().ok();
@@ -17,7 +20,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // let mut round = 0;
     loop {
-        let n: usize =  {
+        let n: usize = {
             let mut buf = String::new();
             io::stdin().read_line(&mut buf)?;
             buf.trim().parse()?
@@ -25,6 +28,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         let judge_info = JudgeInfo {
             submission_id: "s1".to_string(),
+            compile_cmd: None,
+            run_cmd: "/usr/bin/python3 main.py".to_string(),
+            src_file_name: "main.py".to_string(),
             language: "python3".to_string(),
             code: "print('hello ningoj')".to_string(),
             problem_id: "1000".to_string(),
@@ -32,6 +38,12 @@ fn main() -> Result<(), Box<dyn Error>> {
             cpu_time_limit: 10000,
             real_time_limit: 10000,
             memory_limit: 102400,
+            output_limit: None,
+            stack_limit: None,
+            file_limit: None,
+            address_space_limit: None,
+            seccomp_profile: None,
+            mode: JudgeMode::Standard,
         };
 
         let judge_info = serde_json::to_string(&judge_info)?;
@@ -44,4 +56,4 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
         // nc.publish(&subject, &judge_info)?;
     }
-}
\ No newline at end of file
+}
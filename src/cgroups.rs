@@ -3,17 +3,37 @@ use cgroups_rs::{
     cgroup_builder::CgroupBuilder, hierarchies, memory::MemController, Cgroup as CG, CgroupPid,
     Controller, MaxValue,
 };
+use nix::{
+    errno::Errno,
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
 use notify::{watcher, DebouncedEvent, INotifyWatcher, RecursiveMode, Watcher};
 use std::{
     error::Error,
-    fs::OpenOptions,
+    fs::{self, OpenOptions},
     io::Write,
-    sync::mpsc::{self, Receiver},
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver},
+    },
     time::Duration,
 };
+use tracing::warn;
+
+static CGROUP_SEQ: AtomicU64 = AtomicU64::new(0);
 
+// sandboxes of one judgement are created within the same millisecond, the pid and a counter keep
+// their cgroups apart
 fn gen_cgroup_name() -> String {
-    format!("oj-cg-{}", utils::unix_time())
+    format!(
+        "oj-cg-{}-{}-{}",
+        utils::unix_time(),
+        process::id(),
+        CGROUP_SEQ.fetch_add(1, Ordering::Relaxed)
+    )
 }
 
 pub struct Cgroup {
@@ -27,9 +47,20 @@ impl Cgroup {
     //     self.cg.controller_of().expect("get controller failed")
     // }
 
-    pub fn new(memory_limit: Option<u64>, process_limit: Option<u32>) -> Self {
+    // `parent` is a cgroup v2 subtree relative to the hierarchy root, in rootless mode it has
+    // to be delegated to the worker's uid since nothing else is writable
+    pub fn new(
+        memory_limit: Option<u64>,
+        process_limit: Option<u32>,
+        cpus: Option<&[usize]>,
+        parent: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let name = match parent {
+            Some(parent) => format!("{}/{}", parent.trim_matches('/'), gen_cgroup_name()),
+            None => gen_cgroup_name(),
+        };
         let cg = {
-            let mut cg = CgroupBuilder::new(&gen_cgroup_name());
+            let mut cg = CgroupBuilder::new(&name);
             if let Some(memory_limit) = memory_limit {
                 cg = cg.memory().memory_hard_limit(memory_limit as i64).done();
             }
@@ -39,37 +70,91 @@ impl Cgroup {
                     .maximum_number_of_processes(MaxValue::Value(process_limit as i64))
                     .done();
             }
+            if let Some(cpus) = cpus {
+                // e.g. "2,3", the tasks cannot leave these cores even through sched_setaffinity
+                let cpus: Vec<_> = cpus.iter().map(usize::to_string).collect();
+                cg = cg.cpu().cpus(cpus.join(",")).done();
+            }
             let hier = hierarchies::auto();
             cg.build(hier)
         };
 
-        {
-            let path = cg
-                .controller_of::<MemController>()
-                .expect("get controller failed")
-                .path()
-                .join("memory.oom.group");
-            let mut f = OpenOptions::new()
-                .write(true)
-                .open(path)
-                .expect("open memory.oom.group failed");
-            f.write("1".as_bytes())
-                .expect("write memory.oom.group failed");
+        // cgroups-rs swallows creation errors, check the result ourselves
+        let dir = cg
+            .controller_of::<MemController>()
+            .ok_or_else(|| format!("memory controller is not available for cgroup {}", name))?
+            .path()
+            .to_path_buf();
+        if !dir.exists() {
+            return Err(format!(
+                "create cgroup {} failed, is the parent cgroup writable and delegated?",
+                dir.display()
+            )
+            .into());
         }
 
+        OpenOptions::new()
+            .write(true)
+            .open(dir.join("memory.oom.group"))
+            .and_then(|mut f| f.write_all("1".as_bytes()))
+            .map_err(|e| format!("write {}/memory.oom.group failed: {}", dir.display(), e))?;
+
         let (tx, rx) = mpsc::channel();
-        let path = cg
+        let path = dir.join("memory.events.local");
+        let mut _watcher = watcher(tx, Duration::ZERO)?;
+        _watcher.watch(path, RecursiveMode::Recursive)?;
+
+        Ok(Cgroup { cg, _watcher, rx })
+    }
+    fn path(&self) -> PathBuf {
+        self.cg
             .controller_of::<MemController>()
             .expect("get controller failed")
             .path()
-            .join("memory.events.local");
-        let mut _watcher = watcher(tx, Duration::ZERO).expect("Cgroup memory watcher init falied");
-        _watcher
-            .watch(path, RecursiveMode::Recursive)
-            .expect("watcher Cgroup memory failed");
+            .to_path_buf()
+    }
 
-        Cgroup { cg, _watcher, rx }
+    // SIGKILL every process in the cgroup
+    pub fn kill(&self) -> Result<(), Box<dyn Error>> {
+        let path = self.path().join("cgroup.kill");
+        // cgroup.kill is linux 5.14+, fall back to signal the tasks one by one
+        if path.exists() {
+            fs::write(path, "1")?;
+        } else {
+            for task in self.cg.tasks() {
+                match signal::kill(Pid::from_raw(task.pid as i32), Signal::SIGKILL) {
+                    Ok(_) | Err(Errno::ESRCH) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_u64(&self, file: &str) -> Option<u64> {
+        fs::read_to_string(self.path().join(file))
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
     }
+
+    // byte, highest usage since the cgroup was created, None if the kernel lacks memory.peak
+    pub fn memory_peak(&self) -> Option<u64> {
+        self.read_u64("memory.peak")
+    }
+
+    // byte
+    pub fn memory_current(&self) -> Option<u64> {
+        self.read_u64("memory.current")
+    }
+
+    // us, user + system time of all tasks, cpu.stat exists without the cpu controller too
+    pub fn cpu_usage(&self) -> Option<u64> {
+        let stat = fs::read_to_string(self.path().join("cpu.stat")).ok()?;
+        stat.lines()
+            .find_map(|line| line.strip_prefix("usage_usec "))
+            .and_then(|value| value.trim().parse().ok())
+    }
+
     pub fn oom_killed(&self) -> bool {
         self.rx.try_recv().is_ok()
     }
@@ -96,6 +181,9 @@ impl Cgroup {
 impl Drop for Cgroup {
     fn drop(&mut self) {
         while !self.cg.tasks().is_empty() {}
-        self.delete().expect("drop Cgroup failed");
+        // a panic here would take the worker thread down with it
+        if let Err(e) = self.delete() {
+            warn!("delete cgroup {} failed: {}", self.path().display(), e);
+        }
     }
 }
@@ -0,0 +1,159 @@
+use nix::{sys::stat::Mode, unistd};
+use serde::Deserialize;
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fs::{self, Permissions},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+// where the fifo directory shows up inside every sandbox
+pub const FIFO_DIR: &str = "/fifo";
+
+// one end of a fifo, written as "manager" or the index of a program instance
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Endpoint {
+    Manager,
+    Program(u32),
+}
+
+impl TryFrom<String> for Endpoint {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value == "manager" {
+            return Ok(Endpoint::Manager);
+        }
+        value.parse().map(Endpoint::Program).map_err(|_| {
+            format!(
+                "bad fifo endpoint {:?}, want \"manager\" or an index",
+                value
+            )
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Fifo {
+    pub name: String,
+    pub from: Endpoint,
+    pub to: Endpoint,
+}
+
+// the problem's communication.toml, e.g.
+//   instances = 2
+//   [[fifo]]
+//   name = "to_0"
+//   from = "manager"
+//   to = "0"
+// each sandbox gets the paths of the fifos it is an end of appended to its arguments, in the
+// order they are listed
+#[derive(Debug, Deserialize)]
+pub struct Topology {
+    pub instances: u32, // copies of the contestant program
+    #[serde(rename = "fifo", default)]
+    pub fifos: Vec<Fifo>,
+}
+
+impl Topology {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let topology: Topology = toml::from_str(&fs::read_to_string(path)?)?;
+        if topology.instances == 0 {
+            return Err("a communication problem needs at least one program instance".into());
+        }
+        for fifo in &topology.fifos {
+            // the name becomes a path in the fifo directory
+            if fifo.name.is_empty() || fifo.name.contains('/') || fifo.name.starts_with('.') {
+                return Err(format!("bad fifo name {:?}", fifo.name).into());
+            }
+            for endpoint in [fifo.from, fifo.to] {
+                if let Endpoint::Program(index) = endpoint {
+                    if index >= topology.instances {
+                        return Err(format!(
+                            "fifo {} connects instance {} of {}",
+                            fifo.name, index, topology.instances
+                        )
+                        .into());
+                    }
+                }
+            }
+        }
+        Ok(topology)
+    }
+
+    // every endpoint gets a directory of its own in `dir` holding only the fifos it is an end
+    // of, that directory is all its sandbox sees, so it can't open another pair's fifos
+    pub fn endpoint_path(dir: &str, endpoint: Endpoint) -> String {
+        match endpoint {
+            Endpoint::Manager => format!("{}/manager", dir),
+            Endpoint::Program(index) => format!("{}/{}", dir, index),
+        }
+    }
+
+    // mkfifo every pipe in the directory of its writer and hard link it into the reader's,
+    // writable by whichever uid the sandboxes run as
+    pub fn create_fifos(&self, dir: &str) -> Result<(), Box<dyn Error>> {
+        let endpoints =
+            std::iter::once(Endpoint::Manager).chain((0..self.instances).map(Endpoint::Program));
+        for endpoint in endpoints {
+            let path = Self::endpoint_path(dir, endpoint);
+            fs::create_dir_all(&path)?;
+            fs::set_permissions(&path, Permissions::from_mode(0o755))?;
+        }
+        for fifo in &self.fifos {
+            let path = Path::new(&Self::endpoint_path(dir, fifo.from)).join(&fifo.name);
+            unistd::mkfifo(&path, Mode::from_bits_truncate(0o666))?;
+            // mkfifo honours the umask
+            fs::set_permissions(&path, Permissions::from_mode(0o666))?;
+            if fifo.to != fifo.from {
+                fs::hard_link(
+                    &path,
+                    Path::new(&Self::endpoint_path(dir, fifo.to)).join(&fifo.name),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // fifo paths inside the sandbox for `endpoint`
+    pub fn args(&self, endpoint: Endpoint) -> Vec<String> {
+        self.fifos
+            .iter()
+            .filter(|fifo| fifo.from == endpoint || fifo.to == endpoint)
+            .map(|fifo| format!("{}/{}", FIFO_DIR, fifo.name))
+            .collect()
+    }
+}
+
+#[test]
+fn test_topology_args() {
+    let topology: Topology = toml::from_str(
+        r#"
+        instances = 2
+        [[fifo]]
+        name = "to_0"
+        from = "manager"
+        to = "0"
+        [[fifo]]
+        name = "from_0"
+        from = "0"
+        to = "manager"
+        [[fifo]]
+        name = "to_1"
+        from = "manager"
+        to = "1"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        topology.args(Endpoint::Manager),
+        ["/fifo/to_0", "/fifo/from_0", "/fifo/to_1"]
+    );
+    assert_eq!(
+        topology.args(Endpoint::Program(0)),
+        ["/fifo/to_0", "/fifo/from_0"]
+    );
+    assert_eq!(topology.args(Endpoint::Program(1)), ["/fifo/to_1"]);
+}
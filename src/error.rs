@@ -0,0 +1,182 @@
+use nix::errno::Errno;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, io};
+
+// everything that can go wrong in the sandbox itself, as opposed to the sandboxed program.
+// plain strings so it can be serialized along with a result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SandboxError {
+    // parent side
+    Supervisor(String),
+    Cgroup(String),
+    Namespace(String),
+
+    // child side, before execve
+    Setup(String),
+    Redirect(String),
+    Mount(String),
+    Chdir(String),
+    Setuid(String),
+    Seccomp(String),
+    Rlimit(String),
+    Exec(String),
+    Init(String),
+}
+
+impl SandboxError {
+    // for map_err: `.map_err(SandboxError::cause(SandboxError::Mount))`
+    pub fn cause<E: fmt::Display>(kind: fn(String) -> Self) -> impl Fn(E) -> Self {
+        move |e| kind(e.to_string())
+    }
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (stage, cause) = match self {
+            SandboxError::Supervisor(cause) => ("supervise", cause),
+            SandboxError::Cgroup(cause) => ("cgroup", cause),
+            SandboxError::Namespace(cause) => ("namespace", cause),
+            SandboxError::Setup(cause) => ("setup", cause),
+            SandboxError::Redirect(cause) => ("redirect", cause),
+            SandboxError::Mount(cause) => ("mount", cause),
+            SandboxError::Chdir(cause) => ("chdir", cause),
+            SandboxError::Setuid(cause) => ("setuid", cause),
+            SandboxError::Seccomp(cause) => ("seccomp", cause),
+            SandboxError::Rlimit(cause) => ("rlimit", cause),
+            SandboxError::Exec(cause) => ("exec", cause),
+            SandboxError::Init(cause) => ("init", cause),
+        };
+        write!(f, "sandbox {} failed: {}", stage, cause)
+    }
+}
+
+impl Error for SandboxError {}
+
+// where between the clone and execve the child failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Setup,
+    Redirect,
+    Mount,
+    Chdir,
+    Setuid,
+    Seccomp,
+    Rlimit,
+    Exec,
+    Init,
+}
+
+const STAGES: [Stage; 9] = [
+    Stage::Setup,
+    Stage::Redirect,
+    Stage::Mount,
+    Stage::Chdir,
+    Stage::Setuid,
+    Stage::Seccomp,
+    Stage::Rlimit,
+    Stage::Exec,
+    Stage::Init,
+];
+
+// a failure of the child before execve. the child must not allocate (see Command::spawn), so
+// it writes this as a fixed size record to the error pipe and the parent makes a SandboxError
+// of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChildError {
+    pub stage: Stage,
+    pub errno: Errno,
+    pub step: Option<usize>, // the jail step that failed, see mount::Jail
+}
+
+pub const CHILD_ERROR_SIZE: usize = 12;
+
+impl ChildError {
+    // for map_err: `.map_err(ChildError::at(Stage::Setuid))`
+    pub fn at(stage: Stage) -> impl Fn(Errno) -> Self {
+        move |errno| ChildError {
+            stage,
+            errno,
+            step: None,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; CHILD_ERROR_SIZE] {
+        let step = self.step.map_or(-1, |step| step as i32);
+        let mut buf = [0u8; CHILD_ERROR_SIZE];
+        buf[..4].copy_from_slice(&(self.stage as u32).to_ne_bytes());
+        buf[4..8].copy_from_slice(&(self.errno as i32).to_ne_bytes());
+        buf[8..].copy_from_slice(&step.to_ne_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() != CHILD_ERROR_SIZE {
+            return None;
+        }
+        let field = |i: usize| i32::from_ne_bytes(buf[i..i + 4].try_into().unwrap());
+        Some(ChildError {
+            stage: *STAGES.get(field(0) as usize)?,
+            errno: Errno::from_i32(field(4)),
+            step: usize::try_from(field(8)).ok(),
+        })
+    }
+
+    // `describe` names a jail step, e.g. "mount proc on /jail/proc"
+    pub fn into_sandbox_error(self, describe: impl FnOnce(usize) -> String) -> SandboxError {
+        let cause = match self.step {
+            Some(step) => format!("{}: {}", describe(step), self.errno),
+            None => self.errno.to_string(),
+        };
+        let kind = match self.stage {
+            Stage::Setup => SandboxError::Setup,
+            Stage::Redirect => SandboxError::Redirect,
+            Stage::Mount => SandboxError::Mount,
+            Stage::Chdir => SandboxError::Chdir,
+            Stage::Setuid => SandboxError::Setuid,
+            Stage::Seccomp => SandboxError::Seccomp,
+            Stage::Rlimit => SandboxError::Rlimit,
+            Stage::Exec => SandboxError::Exec,
+            Stage::Init => SandboxError::Init,
+        };
+        kind(cause)
+    }
+}
+
+impl From<nix::Error> for SandboxError {
+    fn from(e: nix::Error) -> Self {
+        SandboxError::Supervisor(e.to_string())
+    }
+}
+
+impl From<io::Error> for SandboxError {
+    fn from(e: io::Error) -> Self {
+        SandboxError::Supervisor(e.to_string())
+    }
+}
+
+#[test]
+fn test_sandbox_error_roundtrip() {
+    let e = SandboxError::Exec("ENOENT: No such file or directory".to_string());
+    let buf = serde_json::to_vec(&e).unwrap();
+    let e: SandboxError = serde_json::from_slice(&buf).unwrap();
+    assert_eq!(
+        e.to_string(),
+        "sandbox exec failed: ENOENT: No such file or directory"
+    );
+}
+
+#[test]
+fn test_child_error_record() {
+    let e = ChildError {
+        stage: Stage::Mount,
+        errno: Errno::EPERM,
+        step: Some(3),
+    };
+    let e = ChildError::from_bytes(&e.to_bytes()).unwrap();
+    assert_eq!(
+        e.into_sandbox_error(|step| format!("step {}", step))
+            .to_string(),
+        "sandbox mount failed: step 3: EPERM: Operation not permitted"
+    );
+    assert!(ChildError::from_bytes(&[0; 4]).is_none());
+}
@@ -1,16 +1,40 @@
 use std::fmt::Display;
+use std::fs;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::os::unix::prelude::AsRawFd;
 use std::str::FromStr;
-use std::{fs, panic};
+
+use nix::{fcntl::OFlag, unistd};
 
 use axum::{http::StatusCode, response::IntoResponse, routing, Json, Router};
+use futures_util::future::join_all;
 use serde::{Deserialize, Serialize};
 use tracing::debug;
 
-use crate::run_command::{ExecResult, RunResult};
-use crate::{compare, config, run_command::Command, utils};
+use crate::communication::{Endpoint, Topology, FIFO_DIR};
+use crate::run_command::{ExecResult, RunOption, RunResult};
+use crate::{compare, config, run_command::Command, seccomp_profile, utils, workspace::Workspace};
+
+const DEFAULT_STDERR_LIMIT: u64 = 4096; // bytes
+
+// looked up in the problem's data directory
+const INTERACTOR_FILE_NAME: &str = "interactor";
+const MANAGER_FILE_NAME: &str = "manager";
+const TOPOLOGY_FILE_NAME: &str = "communication.toml";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum JudgeMode {
+    // stdin from <test>.in, stdout compared against <test>.out
+    #[default]
+    Standard,
+    // the problem's interactor talks to the program through its stdin and stdout and decides
+    // the verdict, it gets <test>.in and <test>.out as files
+    Interactive,
+    // several program instances and the problem's manager connected through fifos as laid out
+    // in communication.toml, the manager reads <test>.in on stdin and decides the verdict
+    Communication,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct JudgeInfo {
@@ -24,9 +48,20 @@ pub struct JudgeInfo {
     pub problem_id: String,
     pub data_version: String,
 
-    pub cpu_time_limit: u64,  // ms
-    pub real_time_limit: u64, // ms
-    pub memory_limit: u64,    // kb
+    pub cpu_time_limit: u64,       // ms
+    pub real_time_limit: u64,      // ms
+    pub memory_limit: u64,         // kb
+    pub output_limit: Option<u64>, // kb, judger.output_limit if missing
+    // the ones below fall back to language.<language>.* in the config
+    pub stack_limit: Option<u64>, // kb, memory_limit if missing there too
+    pub file_limit: Option<u64>,  // open files
+    pub address_space_limit: Option<u64>, // kb
+    // chosen for the problem, language.<language>.seccomp_profile otherwise, unrestricted if
+    // neither is set
+    pub seccomp_profile: Option<String>,
+
+    #[serde(default)]
+    pub mode: JudgeMode,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,16 +70,22 @@ pub enum SingleJudgeStatus {
     WrongAnswer,
     RuntimeError,
     TimeLimitExceeded,
+    IdlenessLimitExceeded,
     MemoryLimitExceeded,
+    OutputLimitExceeded,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SingleJudgeResult {
     status: SingleJudgeStatus,
     exit_code: u32,
+    signal: Option<i32>,
     cpu_time: u64,
     real_time: u64,
     memory: u64,
+    stderr: Option<String>, // head of the program's stderr, runtime errors only
+    msg: Option<String>,    // e.g. "SIGSEGV" or "exit code 3" for runtime errors
+    syscall: Option<String>, // the forbidden syscall the program was killed for
 }
 
 impl SingleJudgeResult {
@@ -52,19 +93,44 @@ impl SingleJudgeResult {
         SingleJudgeResult {
             status,
             exit_code: run_result.exit_code,
+            signal: run_result.signal,
             cpu_time: run_result.cpu_time,
             real_time: run_result.real_time,
             memory: run_result.memory,
+            stderr: None,
+            msg: None,
+            syscall: None,
+        }
+    }
+
+    fn runtime_error(run_result: &RunResult, stderr: Option<String>) -> Self {
+        SingleJudgeResult {
+            stderr,
+            msg: run_result.exit_reason(),
+            ..Self::from_run_result(SingleJudgeStatus::RuntimeError, run_result)
         }
     }
 }
 
+// read at most `limit` bytes, the rest of a runaway stderr is of no use to anyone
+fn read_snippet(path: &str, limit: u64) -> Option<String> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(limit)
+        .read_to_end(&mut buf)
+        .ok()?;
+    Some(String::from_utf8_lossy(&buf).into_owned())
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum JudgeStatus {
     Accepted,
     CompileError,
+    IdlenessLimitExceeded,
     Judging,
     MemoryLimitExceeded,
+    OutputLimitExceeded,
     RuntimeError,
     SystemError,
     TimeLimitExceeded,
@@ -84,8 +150,10 @@ impl FromStr for JudgeStatus {
         match s {
             "Accepted" => Ok(JudgeStatus::Accepted),
             "CompileError" => Ok(JudgeStatus::CompileError),
+            "IdlenessLimitExceeded" => Ok(JudgeStatus::IdlenessLimitExceeded),
             "Judging" => Ok(JudgeStatus::Judging),
             "MemoryLimitExceeded" => Ok(JudgeStatus::MemoryLimitExceeded),
+            "OutputLimitExceeded" => Ok(JudgeStatus::OutputLimitExceeded),
             "RuntimeError" => Ok(JudgeStatus::RuntimeError),
             "SystemError" => Ok(JudgeStatus::SystemError),
             "TimeLimitExceeded" => Ok(JudgeStatus::TimeLimitExceeded),
@@ -100,6 +168,7 @@ pub struct JudgeResult {
     pub submission_id: String,
     pub status: JudgeStatus,
     pub exit_code: u32,
+    pub signal: Option<i32>,
     pub cpu_time: u64,
     pub real_time: u64,
     pub memory: u64,
@@ -114,12 +183,27 @@ impl From<SingleJudgeStatus> for JudgeStatus {
             SingleJudgeStatus::WrongAnswer => JudgeStatus::WrongAnswer,
             SingleJudgeStatus::RuntimeError => JudgeStatus::RuntimeError,
             SingleJudgeStatus::TimeLimitExceeded => JudgeStatus::TimeLimitExceeded,
+            SingleJudgeStatus::IdlenessLimitExceeded => JudgeStatus::IdlenessLimitExceeded,
             SingleJudgeStatus::MemoryLimitExceeded => JudgeStatus::MemoryLimitExceeded,
+            SingleJudgeStatus::OutputLimitExceeded => JudgeStatus::OutputLimitExceeded,
         }
     }
 }
 
 impl JudgeResult {
+    fn system_error(submission_id: String, msg: String) -> Self {
+        JudgeResult::new(
+            submission_id,
+            JudgeStatus::SystemError,
+            0,
+            0,
+            0,
+            0,
+            vec![],
+            Some(msg),
+        )
+    }
+
     fn new(
         submission_id: String,
         status: JudgeStatus,
@@ -134,6 +218,7 @@ impl JudgeResult {
             submission_id,
             status,
             exit_code,
+            signal: None,
             cpu_time,
             real_time,
             memory,
@@ -143,33 +228,179 @@ impl JudgeResult {
     }
 }
 
-pub fn judge(judge_info: JudgeInfo) -> JudgeResult {
+// what every sandboxed process of a judgement shares, whoever wrote it
+struct SandboxSettings {
+    env: Vec<String>,
+    uid: u32,
+    cpu_time_limit: u64,  // ms
+    real_time_limit: u64, // ms
+    memory_limit: u64,    // kb
+    mount_paths: Vec<String>,
+    overlay: bool,
+    rootless: bool,
+    cgroup_parent: Option<String>,
+    tmpfs_size: Option<u64>,
+    cpu: Option<usize>,
+}
+
+impl SandboxSettings {
+    // the settings win over whatever `option` already holds
+    fn command(&self, option: RunOption, jail_path: &str) -> Command {
+        let mut cmd = Command::from(option);
+        cmd.env(self.env.clone())
+            .uid(self.uid)
+            .cpu_time(self.cpu_time_limit)
+            .real_time(self.real_time_limit)
+            .memory(self.memory_limit)
+            .jail_path(jail_path)
+            .mounts(self.mount_paths.clone())
+            .overlay(self.overlay)
+            .pid_namespace(true)
+            .rootless(self.rootless);
+        if let Some(cgroup_parent) = &self.cgroup_parent {
+            cmd.cgroup_parent(cgroup_parent);
+        }
+        if let Some(tmpfs_size) = self.tmpfs_size {
+            cmd.tmpfs(tmpfs_size);
+        }
+        if let Some(cpu) = self.cpu {
+            cmd.cpus(vec![cpu]);
+        }
+        cmd
+    }
+}
+
+// `cpu` is the core of the worker slot running the judgement, every sandbox of it is pinned there
+pub async fn judge(judge_info: JudgeInfo, cpu: Option<usize>) -> JudgeResult {
     debug!("{:?}", judge_info);
-    let config = config::get_config();
+    // a snapshot, the lock guard must not be held across the awaits below
+    let config = config::get_config().clone();
 
     let jail_path = config.get_string("sandbox.jail_path").unwrap();
+    let mount_paths: Vec<String> = config.get("sandbox.mount_paths").unwrap_or_default();
+    let rootless = config.get_bool("sandbox.rootless").unwrap_or(false);
+    let overlay = config.get_bool("sandbox.overlay").unwrap_or(true);
+    let tmpfs_size = config
+        .get_int("sandbox.tmpfs_size")
+        .ok()
+        .map(|size| size as u64);
+    let cgroup_parent = config.get_string("sandbox.cgroup_parent").ok();
+    let env: Vec<String> = config
+        .get(&format!("language.{}.env", judge_info.language))
+        .or_else(|_| config.get("sandbox.env"))
+        .unwrap_or_default();
+    let output_limit = judge_info.output_limit.or_else(|| {
+        config
+            .get_int("judger.output_limit")
+            .ok()
+            .map(|limit| limit as u64)
+    });
+    let language_limit = |key: &str| {
+        config
+            .get_int(&format!("language.{}.{}", judge_info.language, key))
+            .ok()
+            .map(|limit| limit as u64)
+    };
+    let stack_limit = judge_info
+        .stack_limit
+        .or_else(|| language_limit("stack_limit"))
+        .unwrap_or(judge_info.memory_limit);
+    let file_limit = judge_info
+        .file_limit
+        .or_else(|| language_limit("file_limit"));
+    let address_space_limit = judge_info
+        .address_space_limit
+        .or_else(|| language_limit("address_space_limit"));
+    // language.<lang>.compile and .run, the base of the compiler's and the program's run
+    let language_option = |key: &str| match config
+        .get::<RunOption>(&format!("language.{}.{}", judge_info.language, key))
+    {
+        Ok(option) => Ok(option),
+        Err(::config::ConfigError::NotFound(_)) => Ok(RunOption::default()),
+        Err(e) => Err(format!("language.{}.{}: {}", judge_info.language, key, e)),
+    };
+    let (compile_option, run_option) = match (language_option("compile"), language_option("run")) {
+        (Ok(compile_option), Ok(run_option)) => (compile_option, run_option),
+        (Err(e), _) | (_, Err(e)) => return JudgeResult::system_error(judge_info.submission_id, e),
+    };
+    let seccomp_profile = judge_info.seccomp_profile.clone().or_else(|| {
+        config
+            .get_string(&format!("language.{}.seccomp_profile", judge_info.language))
+            .ok()
+    });
+    let seccomp_profile = match seccomp_profile.as_deref().map(seccomp_profile::get) {
+        Some(Err(e)) => return JudgeResult::system_error(judge_info.submission_id, e),
+        profile => profile.and_then(Result::ok),
+    };
+    let stderr_limit = config
+        .get_int("judger.stderr_limit")
+        .map_or(DEFAULT_STDERR_LIMIT, |limit| limit as u64);
+    // a program that sat a whole cpu time limit without computing won't finish
+    let idle_time_limit = config
+        .get_int("judger.idle_time_limit")
+        .map_or(judge_info.cpu_time_limit, |limit| limit as u64);
+
+    let workspace = match Workspace::new(&jail_path, &judge_info.submission_id) {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            return JudgeResult::new(
+                judge_info.submission_id.clone(),
+                JudgeStatus::SystemError,
+                0,
+                0,
+                0,
+                0,
+                vec![],
+                Some(format!("create workspace failed: {}", e)),
+            )
+        }
+    };
+    let box_path = workspace.box_path();
 
     // write code to file
     {
-        let src_file_name = judge_info.src_file_name;
-        let src_file_path = format!("{}{}", jail_path, src_file_name);
+        let src_file_path = workspace.src(&judge_info.src_file_name);
         let mut src_file = File::create(&src_file_path).unwrap();
         write!(src_file, "{}", judge_info.code).unwrap();
     }
 
     // compile src code if need
     if let Some(compile_cmd) = judge_info.compile_cmd {
-        let args: Vec<_> = compile_cmd.split(" ").collect();
-        let cmd_path = args[0];
-        let output_file_path = config.get_string("judger.compile_output_file").unwrap();
+        let args: Vec<_> = compile_cmd.split(" ").map(String::from).collect();
+        let output_file_path = workspace.compile_output();
         let output_file = File::create(&output_file_path).unwrap();
         let output_fd = output_file.as_raw_fd();
-        let res = Command::new(cmd_path)
-            .args(args)
-            .exec_path(&jail_path)
+        let mut cmd = Command::from(RunOption {
+            cmd: args[0].clone(),
+            ..compile_option
+        });
+        cmd.args(args)
+            .env(env.clone())
+            .exec_path(&box_path)
             .stdout(output_fd as u32)
-            .run()
-            .unwrap();
+            .stderr(output_fd as u32)
+            .rootless(rootless);
+        if let Some(cgroup_parent) = &cgroup_parent {
+            cmd.cgroup_parent(cgroup_parent);
+        }
+        if let Some(cpu) = cpu {
+            cmd.cpus(vec![cpu]);
+        }
+        let res = match cmd.run_async().await {
+            Ok(res) => res,
+            Err(e) => {
+                return JudgeResult::new(
+                    judge_info.submission_id.clone(),
+                    JudgeStatus::SystemError,
+                    0,
+                    0,
+                    0,
+                    0,
+                    vec![],
+                    Some(e.to_string()),
+                )
+            }
+        };
         debug!("compile result: {:?}", res);
         if res.result != ExecResult::Ok {
             let compile_error_msg = fs::read_to_string(&output_file_path).unwrap();
@@ -199,7 +430,7 @@ pub fn judge(judge_info: JudgeInfo) -> JudgeResult {
     let paths = fs::read_dir(&data_path);
     let paths = match paths {
         Ok(paths) => paths.filter_map(Result::ok)
-        .filter(|path| path.path().extension().unwrap() == "in"),
+        .filter(|path| path.path().extension().is_some_and(|ext| ext == "in")),
         Err(_) => return JudgeResult::new(
             judge_info.submission_id.clone(),
             JudgeStatus::SystemError,
@@ -211,6 +442,83 @@ pub fn judge(judge_info: JudgeInfo) -> JudgeResult {
             Some("data not found".to_string()),
         )
     };
+    // the interactor or manager ships with the data, it runs from its own box like the program
+    let helper_box_path = workspace.helper_box_path();
+    let helper = match judge_info.mode {
+        JudgeMode::Standard => None,
+        JudgeMode::Interactive => Some(INTERACTOR_FILE_NAME),
+        JudgeMode::Communication => Some(MANAGER_FILE_NAME),
+    };
+    if let Some(helper) = helper {
+        let source = format!("{}/{}", data_path, helper);
+        if let Err(e) = fs::copy(&source, format!("{}/{}", helper_box_path, helper)) {
+            return JudgeResult::system_error(
+                judge_info.submission_id,
+                format!("{} not usable: {}", source, e),
+            );
+        }
+    }
+    let helper_cmd = format!("/{}", helper.unwrap_or_default());
+    let fifo_path = workspace.fifo_path();
+    let topology = match judge_info.mode {
+        JudgeMode::Communication => {
+            let topology = Topology::load(&format!("{}/{}", data_path, TOPOLOGY_FILE_NAME))
+                .and_then(|topology| {
+                    topology.create_fifos(&fifo_path)?;
+                    Ok(topology)
+                });
+            match topology {
+                Ok(topology) => Some(topology),
+                Err(e) => {
+                    return JudgeResult::system_error(
+                        judge_info.submission_id,
+                        format!("communication topology: {}", e),
+                    )
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let settings = SandboxSettings {
+        env,
+        uid: config.get_int("judger.exec_user_uid").unwrap() as u32,
+        cpu_time_limit: judge_info.cpu_time_limit,
+        real_time_limit: judge_info.real_time_limit,
+        memory_limit: judge_info.memory_limit,
+        mount_paths,
+        overlay,
+        rootless,
+        cgroup_parent,
+        tmpfs_size,
+        cpu,
+    };
+    // what only the contestant's program gets on top of the settings
+    let program = |jail_path| {
+        let option = RunOption {
+            cmd: cmd_path.to_string(),
+            ..run_option.clone()
+        };
+        let mut cmd = settings.command(option, jail_path);
+        cmd.args(args.clone()).stack(stack_limit);
+        // with an interactor or manager the program waits on the other side by design, and all
+        // of them share one core, so low cpu usage says nothing there
+        if let JudgeMode::Standard = judge_info.mode {
+            cmd.idle_time(idle_time_limit);
+        }
+        if let Some(file_limit) = file_limit {
+            cmd.files(file_limit);
+        }
+        if let Some(address_space_limit) = address_space_limit {
+            cmd.address_space(address_space_limit);
+        }
+        if let Some(profile) = seccomp_profile {
+            cmd.syscall_rules(profile.syscalls.clone())
+                .syscall_default(profile.default_action);
+        }
+        cmd
+    };
+
     let mut results: Vec<SingleJudgeResult> = Vec::new();
     let mut max_cpu_time = 0;
     let mut max_real_time = 0;
@@ -221,37 +529,235 @@ pub fn judge(judge_info: JudgeInfo) -> JudgeResult {
         let input_file = File::open(input_file_path.path()).unwrap();
         let input_fd = input_file.as_raw_fd();
 
-        let output_file_path = config.get_string("judger.user_output_file").unwrap();
+        let std_out_file_path = input_file_path
+            .path()
+            .with_extension("out")
+            .into_os_string()
+            .into_string()
+            .unwrap();
+
+        let test = input_file_path
+            .path()
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let output_file_path = workspace.output(&test);
         let output_file = File::create(&output_file_path).unwrap();
         let output_fd = output_file.as_raw_fd();
 
-        let uid = config.get_int("judger.exec_user_uid").unwrap();
+        let error_file_path = workspace.error(&test);
+        let error_file = File::create(&error_file_path).unwrap();
+        let error_fd = error_file.as_raw_fd();
+
+        let helper_error_path = workspace.helper_error(&test);
+        let helper_error_file = File::create(&helper_error_path).unwrap();
+        let helper_error_fd = helper_error_file.as_raw_fd();
 
         debug!("cmd_path: {}", cmd_path);
-        let res = Command::new(cmd_path)
-            .args(args.clone())
-            .uid(uid as u32)
-            .cpu_time(judge_info.cpu_time_limit)
-            .real_time(judge_info.real_time_limit)
-            .memory(judge_info.memory_limit)
-            .jail_path(&jail_path)
-            .stdin(input_fd as u32)
-            .stdout(output_fd as u32)
-            .run()
-            .expect("run command error");
+        // the program's result, where its stderr went and the interactor's or manager's result
+        let (res, stderr_path, helper_res) = match judge_info.mode {
+            JudgeMode::Standard => {
+                let mut cmd = program(&box_path);
+                cmd.stdin(input_fd as u32)
+                    .stdout(output_fd as u32)
+                    .stderr(error_fd as u32);
+                if let Some(output_limit) = output_limit {
+                    cmd.output(output_limit);
+                }
+                (cmd.run_async().await, error_file_path, None)
+            }
+            JudgeMode::Interactive => {
+                // two pipes crossing the program's and the interactor's stdin and stdout
+                let (to_program, to_interactor) = match (
+                    unistd::pipe2(OFlag::O_CLOEXEC),
+                    unistd::pipe2(OFlag::O_CLOEXEC),
+                ) {
+                    (Ok(to_program), Ok(to_interactor)) => (to_program, to_interactor),
+                    (res, other) => {
+                        for (rx, tx) in [res, other].into_iter().flatten() {
+                            let _ = unistd::close(rx);
+                            let _ = unistd::close(tx);
+                        }
+                        return JudgeResult::system_error(
+                            judge_info.submission_id,
+                            "create interactor pipes failed".to_string(),
+                        );
+                    }
+                };
+                let mut cmd = program(&box_path);
+                cmd.stdin(to_program.0 as u32)
+                    .stdout(to_interactor.1 as u32)
+                    .stderr(error_fd as u32)
+                    .give_fds(vec![to_program.0 as u32, to_interactor.1 as u32]);
+
+                // testlib style: interactor <input> <output> <answer>, the files are passed as
+                // fds since the data directory is not in its jail, the answer is optional
+                let answer_file = File::open(&std_out_file_path).ok();
+                let mut keep_fds = vec![input_fd as u32];
+                keep_fds.extend(answer_file.as_ref().map(|file| file.as_raw_fd() as u32));
+                let input_arg = format!("/proc/self/fd/{}", input_fd);
+                let answer_arg = match &answer_file {
+                    Some(file) => format!("/proc/self/fd/{}", file.as_raw_fd()),
+                    None => "/dev/null".to_string(),
+                };
+                let mut interactor =
+                    settings.command(RunOption::new(&helper_cmd), &helper_box_path);
+                interactor
+                    .args(vec![
+                        INTERACTOR_FILE_NAME,
+                        &input_arg,
+                        "/dev/null",
+                        &answer_arg,
+                    ])
+                    .stdin(to_interactor.0 as u32)
+                    .stdout(to_program.1 as u32)
+                    .stderr(helper_error_fd as u32)
+                    .keep_fds(keep_fds)
+                    .give_fds(vec![to_interactor.0 as u32, to_program.1 as u32]);
+                let (res, interactor_res) = tokio::join!(cmd.run_async(), interactor.run_async());
+                let interactor_res = match interactor_res {
+                    Ok(interactor_res) => interactor_res,
+                    Err(e) => {
+                        return JudgeResult::system_error(
+                            judge_info.submission_id,
+                            format!("interactor: {}", e),
+                        )
+                    }
+                };
+                (res, error_file_path, Some(interactor_res))
+            }
+            JudgeMode::Communication => {
+                let topology = topology.as_ref().unwrap();
+                let null = File::open("/dev/null").unwrap();
+                let manager_args = topology.args(Endpoint::Manager);
+                let mut manager = settings.command(RunOption::new(&helper_cmd), &helper_box_path);
+                manager
+                    .args(
+                        std::iter::once(MANAGER_FILE_NAME)
+                            .chain(manager_args.iter().map(String::as_str)),
+                    )
+                    .bind(
+                        Topology::endpoint_path(&fifo_path, Endpoint::Manager),
+                        FIFO_DIR,
+                    )
+                    .stdin(input_fd as u32)
+                    .stdout(output_fd as u32)
+                    .stderr(helper_error_fd as u32);
+
+                // every instance logs stdout and stderr to its own <test>.<index>.err
+                let instance_args: Vec<_> = (0..topology.instances)
+                    .map(|index| topology.args(Endpoint::Program(index)))
+                    .collect();
+                let mut logs = vec![];
+                for index in 0..topology.instances {
+                    let log_path = workspace.error(&format!("{}.{}", test, index));
+                    let log = File::create(&log_path).unwrap();
+                    logs.push((log_path, log));
+                }
+                let mut cmds = vec![manager];
+                for (index, (fifo_args, (_, log))) in instance_args.iter().zip(&logs).enumerate() {
+                    let endpoint = Endpoint::Program(index as u32);
+                    let mut cmd = program(&box_path);
+                    cmd.args(
+                        args.iter()
+                            .copied()
+                            .chain(fifo_args.iter().map(String::as_str)),
+                    )
+                    .bind(Topology::endpoint_path(&fifo_path, endpoint), FIFO_DIR)
+                    .stdin(null.as_raw_fd() as u32)
+                    .stdout(log.as_raw_fd() as u32)
+                    .stderr(log.as_raw_fd() as u32);
+                    if let Some(output_limit) = output_limit {
+                        cmd.output(output_limit);
+                    }
+                    cmds.push(cmd);
+                }
+
+                let mut runs = join_all(cmds.iter_mut().map(|cmd| cmd.run_async())).await;
+                let manager_res = match runs.remove(0) {
+                    Ok(manager_res) => manager_res,
+                    Err(e) => {
+                        return JudgeResult::system_error(
+                            judge_info.submission_id,
+                            format!("manager: {}", e),
+                        )
+                    }
+                };
+                let mut instances = vec![];
+                for (run, (log_path, _)) in runs.into_iter().zip(logs) {
+                    match run {
+                        Ok(res) => instances.push((res, log_path)),
+                        Err(e) => {
+                            return JudgeResult::system_error(
+                                judge_info.submission_id,
+                                e.to_string(),
+                            )
+                        }
+                    }
+                }
+                // the first instance that failed speaks for all, otherwise the slowest one
+                let position = instances
+                    .iter()
+                    .position(|(res, _)| res.result != ExecResult::Ok)
+                    .or_else(|| {
+                        (0..instances.len()).max_by_key(|&index| instances[index].0.cpu_time)
+                    })
+                    .unwrap();
+                let (res, log_path) = instances.swap_remove(position);
+                (Ok(res), log_path, Some(manager_res))
+            }
+        };
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => return JudgeResult::system_error(judge_info.submission_id, e.to_string()),
+        };
         debug!("run: {:?}", res);
 
+        // testlib exit codes, 1 wrong answer and 2 presentation error are the program's fault,
+        // anything else means the interactor or manager itself broke
+        let helper_accepted = match &helper_res {
+            Some(helper_res) if helper_res.signal.is_none() => match helper_res.exit_code {
+                0 => Some(true),
+                1 | 2 => Some(false),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let (Some(helper_res), None, ExecResult::Ok) = (&helper_res, helper_accepted, res.result)
+        {
+            debug!("helper: {:?}", helper_res);
+            let stderr = read_snippet(&helper_error_path, stderr_limit).unwrap_or_default();
+            return JudgeResult::system_error(
+                judge_info.submission_id,
+                format!(
+                    "{} failed with {}: {}",
+                    helper.unwrap_or_default(),
+                    helper_res.exit_reason().unwrap_or_default(),
+                    stderr
+                ),
+            );
+        }
+
         let single_judge_result = match res.result {
+            // the other side giving up first usually leaves the program dying on a broken pipe
+            ExecResult::Ok | ExecResult::RuntimeError | ExecResult::SyscallLimitExceeded
+                if helper_accepted == Some(false) =>
+            {
+                SingleJudgeResult {
+                    msg: read_snippet(&helper_error_path, stderr_limit),
+                    ..SingleJudgeResult::from_run_result(SingleJudgeStatus::WrongAnswer, &res)
+                }
+            }
             ExecResult::Ok => {
-                let std_out_file_path = input_file_path
-                    .path()
-                    .with_extension("out")
-                    .into_os_string()
-                    .into_string()
-                    .unwrap();
-                debug!("{} {}", std_out_file_path, output_file_path);
-                let ok =
-                    compare::compare_two_file(&std_out_file_path, &output_file_path, None).unwrap();
+                let ok = match helper_accepted {
+                    Some(accepted) => accepted,
+                    None => {
+                        debug!("{} {}", std_out_file_path, output_file_path);
+                        compare::compare_two_file(&std_out_file_path, &output_file_path, None)
+                            .unwrap()
+                    }
+                };
                 if ok {
                     SingleJudgeResult::from_run_result(SingleJudgeStatus::Accepted, &res)
                 } else {
@@ -264,14 +770,34 @@ pub fn judge(judge_info: JudgeInfo) -> JudgeResult {
             ExecResult::RealTimeLimitExceeded => {
                 SingleJudgeResult::from_run_result(SingleJudgeStatus::TimeLimitExceeded, &res)
             }
+            ExecResult::IdlenessLimitExceeded => SingleJudgeResult {
+                msg: Some("the program is waiting for input or sleeping".to_string()),
+                ..SingleJudgeResult::from_run_result(SingleJudgeStatus::IdlenessLimitExceeded, &res)
+            },
             ExecResult::MemoryLimitExceeded => {
                 SingleJudgeResult::from_run_result(SingleJudgeStatus::MemoryLimitExceeded, &res)
             }
+            ExecResult::OutputLimitExceeded => {
+                SingleJudgeResult::from_run_result(SingleJudgeStatus::OutputLimitExceeded, &res)
+            }
             ExecResult::SyscallLimitExceeded => {
-                SingleJudgeResult::from_run_result(SingleJudgeStatus::RuntimeError, &res)
+                let syscall = res.syscall_name();
+                SingleJudgeResult {
+                    msg: Some(match &syscall {
+                        Some(syscall) => {
+                            format!("killed for calling forbidden syscall {}", syscall)
+                        }
+                        None => "killed for calling a forbidden syscall".to_string(),
+                    }),
+                    syscall,
+                    ..SingleJudgeResult::runtime_error(
+                        &res,
+                        read_snippet(&stderr_path, stderr_limit),
+                    )
+                }
             }
             ExecResult::RuntimeError => {
-                SingleJudgeResult::from_run_result(SingleJudgeStatus::RuntimeError, &res)
+                SingleJudgeResult::runtime_error(&res, read_snippet(&stderr_path, stderr_limit))
             }
         };
         max_cpu_time = max_cpu_time.max(single_judge_result.cpu_time);
@@ -282,16 +808,19 @@ pub fn judge(judge_info: JudgeInfo) -> JudgeResult {
 
         let status = JudgeStatus::from(single_judge_result.status);
         if status != JudgeStatus::Accepted {
-            return JudgeResult::new(
-                judge_info.submission_id.clone(),
-                status,
-                single_judge_result.exit_code,
-                max_cpu_time,
-                max_real_time,
-                max_memory,
-                results,
-                None,
-            );
+            return JudgeResult {
+                signal: single_judge_result.signal,
+                ..JudgeResult::new(
+                    judge_info.submission_id.clone(),
+                    status,
+                    single_judge_result.exit_code,
+                    max_cpu_time,
+                    max_real_time,
+                    max_memory,
+                    results,
+                    single_judge_result.msg,
+                )
+            };
         }
     }
     JudgeResult::new(
@@ -308,7 +837,8 @@ pub fn judge(judge_info: JudgeInfo) -> JudgeResult {
 
 pub async fn judge_handler(Json(judge_info): Json<JudgeInfo>) -> impl IntoResponse {
     let submission_id = judge_info.submission_id.clone();
-    let res = panic::catch_unwind(|| judge(judge_info));
+    // a panicking judgement only takes its own task down
+    let res = tokio::spawn(judge(judge_info, None)).await;
     match res {
         Ok(res) => (StatusCode::OK, utils::gen_response(0, res)),
         Err(e) => {
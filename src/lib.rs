@@ -1,13 +1,17 @@
 pub mod cgroups;
+pub mod communication;
 pub mod compare;
 pub mod compiler;
 pub mod config;
 pub mod data;
+pub mod error;
 pub mod judge;
-pub mod languages;
+pub mod mount;
 pub mod os;
 pub mod proto;
 pub mod run_command;
 pub mod seccomp;
+pub mod seccomp_profile;
 pub mod timer;
 pub mod utils;
+pub mod workspace;
@@ -1,20 +1,47 @@
 use oj_judger::{
     config,
     judge::{self, JudgeInfo},
+    os, seccomp_profile,
 };
 use rayon::ThreadPoolBuilder;
 use std::{
     env,
     error::Error,
-    mem,
+    fs, mem,
     sync::{Arc, Mutex},
     thread,
 };
-use tracing::debug;
+use tokio::runtime;
+use tracing::{debug, warn};
 use tracing_subscriber::fmt;
 
+// the jail itself is assembled per run in a private mount namespace (see mount::Jail),
+// the host only needs the root directory to exist
 pub fn init() {
-    // TODO mount bin and lib to jail here
+    // a broken profile stops the worker here instead of failing every judgement using it
+    seccomp_profile::load().expect("invalid seccomp profiles");
+
+    let config = config::get_config();
+    let jail_path = config
+        .get_string("sandbox.jail_path")
+        .expect("sandbox.jail_path must be set");
+    fs::create_dir_all(jail_path).expect("create jail directory failed");
+
+    if config.get_bool("sandbox.rootless").unwrap_or(false) {
+        warn!("rootless mode: sandboxed programs run as the worker's own uid on the host");
+        let cgroup_parent = config.get_string("sandbox.cgroup_parent").ok();
+        let pinned = config
+            .get::<Vec<usize>>("worker.cpus")
+            .is_ok_and(|cpus| !cpus.is_empty());
+        let missing = os::missing_rootless_features(cgroup_parent.as_deref(), pinned);
+        // every judgement would end in a SystemError, better not to take any
+        if !missing.is_empty() {
+            panic!(
+                "rootless mode is not usable on this host:\n  {}",
+                missing.join("\n  ")
+            );
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -31,6 +58,16 @@ fn main() -> Result<(), Box<dyn Error>> {
     let sub = nc.queue_subscribe(&subject, "default_queue")?;
 
     let thread_number = config.get_int("worker.thread_number")? as usize;
+    // one core per worker thread, the sandboxes of concurrent judgements never share one
+    let cpus: Vec<usize> = config.get("worker.cpus").unwrap_or_default();
+    if !cpus.is_empty() && cpus.len() < thread_number {
+        return Err(format!(
+            "worker.cpus lists {} cores for {} worker threads",
+            cpus.len(),
+            thread_number
+        )
+        .into());
+    }
 
     let pool = ThreadPoolBuilder::new()
         .num_threads(thread_number)
@@ -49,9 +86,16 @@ fn main() -> Result<(), Box<dyn Error>> {
                 match judge_info {
                     Ok(judge_info) => {
                         let free_thread_number = Arc::clone(&free_thread_number);
+                        let cpus = cpus.clone();
                         debug!("{:?}", judge_info);
                         let result = pool.install(move || {
-                            let result = judge::judge(judge_info);
+                            let cpu = rayon::current_thread_index()
+                                .and_then(|slot| cpus.get(slot).copied());
+                            let runtime = runtime::Builder::new_current_thread()
+                                .enable_all()
+                                .build()
+                                .expect("build judge runtime failed");
+                            let result = runtime.block_on(judge::judge(judge_info, cpu));
                             *free_thread_number.lock().unwrap() += 1;
                             debug!("wuhu, i am free {:?}", thread::current().id());
                             result
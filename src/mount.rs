@@ -0,0 +1,374 @@
+use nix::{
+    errno::Errno,
+    fcntl::{self, OFlag},
+    mount::{self, MntFlags, MsFlags},
+    sys::{
+        stat::Mode,
+        statvfs::{self, FsFlags},
+    },
+    unistd,
+};
+use std::{
+    error::Error,
+    ffi::CString,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+const DEV_NODES: &[&str] = &["/dev/null", "/dev/zero", "/dev/urandom"];
+
+// one syscall or so of the jail setup, every path is converted up front
+#[derive(Debug)]
+enum Step {
+    Mount {
+        source: Option<CString>,
+        target: CString,
+        fstype: Option<CString>,
+        flags: MsFlags,
+        data: Option<CString>,
+    },
+    // follows the bind mount of a read-only path
+    RemountReadOnly(CString),
+    // an existing directory is fine
+    Mkdir(CString),
+    // an empty file to bind a file onto, an existing one is fine
+    Touch(CString),
+    // stack the old root under the new one and detach it, see pivot_root(2)
+    PivotRoot(CString),
+}
+
+impl Step {
+    fn apply(&self) -> Result<(), Errno> {
+        match self {
+            Step::Mount {
+                source,
+                target,
+                fstype,
+                flags,
+                data,
+            } => mount::mount(
+                source.as_deref(),
+                target.as_c_str(),
+                fstype.as_deref(),
+                *flags,
+                data.as_deref(),
+            ),
+            Step::RemountReadOnly(target) => {
+                // a read-only remount has to keep the flags the kernel locked on the source
+                // mount (nosuid, nodev, atime...), the statvfs bits differ from the MsFlags ones
+                let flags = statvfs::statvfs(target.as_c_str())?.flags();
+                let mut locked = MsFlags::empty();
+                for (st, ms) in [
+                    (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+                    (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+                    (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+                    (FsFlags::ST_NOATIME, MsFlags::MS_NOATIME),
+                    (FsFlags::ST_NODIRATIME, MsFlags::MS_NODIRATIME),
+                    (FsFlags::ST_RELATIME, MsFlags::MS_RELATIME),
+                ] {
+                    if flags.contains(st) {
+                        locked |= ms;
+                    }
+                }
+                // neither means strictatime, a remount without any atime flag would ask for
+                // relatime
+                if !flags.intersects(FsFlags::ST_NOATIME | FsFlags::ST_RELATIME) {
+                    locked |= MsFlags::MS_STRICTATIME;
+                }
+                mount::mount(
+                    None::<&str>,
+                    target.as_c_str(),
+                    None::<&str>,
+                    MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY | locked,
+                    None::<&str>,
+                )
+            }
+            Step::Mkdir(path) => {
+                match unistd::mkdir(path.as_c_str(), Mode::from_bits_truncate(0o777)) {
+                    Ok(_) | Err(Errno::EEXIST) => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }
+            Step::Touch(path) => {
+                let fd = fcntl::open(
+                    path.as_c_str(),
+                    OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_CLOEXEC,
+                    Mode::from_bits_truncate(0o644),
+                )?;
+                unistd::close(fd)
+            }
+            Step::PivotRoot(root) => {
+                unistd::chdir(root.as_c_str())?;
+                unistd::pivot_root(".", ".")?;
+                mount::umount2(".", MntFlags::MNT_DETACH)?;
+                unistd::chdir("/")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Mount {
+                source,
+                target,
+                fstype,
+                ..
+            } => {
+                let what = fstype.as_ref().or(source.as_ref());
+                match what {
+                    Some(what) => write!(f, "mount {:?} on {:?}", what, target),
+                    None => write!(f, "change propagation of {:?}", target),
+                }
+            }
+            Step::RemountReadOnly(target) => write!(f, "remount {:?} read-only", target),
+            Step::Mkdir(path) => write!(f, "mkdir {:?}", path),
+            Step::Touch(path) => write!(f, "create {:?}", path),
+            Step::PivotRoot(root) => write!(f, "pivot_root into {:?}", root),
+        }
+    }
+}
+
+fn cstring(path: &Path) -> Result<CString, Box<dyn Error>> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+// the jail setup of one run, worked out before the sandbox child is cloned. the child must
+// not allocate, so all it does is apply the steps in order
+#[derive(Debug, Default)]
+pub struct Jail {
+    steps: Vec<Step>,
+}
+
+impl Jail {
+    fn mount(
+        &mut self,
+        source: Option<&Path>,
+        target: &Path,
+        fstype: Option<&str>,
+        flags: MsFlags,
+        data: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.steps.push(Step::Mount {
+            source: source.map(cstring).transpose()?,
+            target: cstring(target)?,
+            fstype: fstype.map(CString::new).transpose()?,
+            flags,
+            data: data.map(CString::new).transpose()?,
+        });
+        Ok(())
+    }
+
+    fn bind(
+        &mut self,
+        source: &Path,
+        target: &Path,
+        read_only: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        self.mount(
+            Some(source),
+            target,
+            None,
+            MsFlags::MS_BIND | MsFlags::MS_REC,
+            None,
+        )?;
+        if read_only {
+            self.steps.push(Step::RemountReadOnly(cstring(target)?));
+        }
+        Ok(())
+    }
+
+    // `path` and every directory between it and `base`, which has to exist
+    fn mkdir_all(&mut self, base: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+        let relative = path.strip_prefix(base)?;
+        let mut dir = base.to_path_buf();
+        for component in relative.components() {
+            dir.push(component);
+            self.steps.push(Step::Mkdir(cstring(&dir)?));
+        }
+        Ok(())
+    }
+
+    fn mount_fs(
+        &mut self,
+        fstype: &str,
+        base: &Path,
+        target: &Path,
+        flags: MsFlags,
+        data: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.mkdir_all(base, target)?;
+        self.mount(Some(Path::new(fstype)), target, Some(fstype), flags, data)
+    }
+
+    // stack a throwaway tmpfs upper layer over the read-only `lower`, the merged tree is
+    // mounted at <lower>.overlay/root which is returned. only this namespace ever sees the
+    // mounts, the empty <lower>.overlay directory is all that is left on the host
+    fn overlay(&mut self, lower: &Path, size_data: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let mut scratch = lower.as_os_str().to_owned();
+        scratch.push(".overlay");
+        let scratch = PathBuf::from(scratch);
+        let parent = scratch.parent().ok_or("the jail can't be /")?;
+        self.mount_fs(
+            "tmpfs",
+            parent,
+            &scratch,
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+            Some(size_data),
+        )?;
+
+        let (upper, work, root) = (
+            scratch.join("upper"),
+            scratch.join("work"),
+            scratch.join("root"),
+        );
+        for dir in [&upper, &work, &root] {
+            self.steps.push(Step::Mkdir(cstring(dir)?));
+        }
+        let data = format!(
+            "lowerdir={},upperdir={},workdir={}",
+            lower.display(),
+            upper.display(),
+            work.display()
+        );
+        self.mount(
+            Some(Path::new("overlay")),
+            &root,
+            Some("overlay"),
+            MsFlags::empty(),
+            Some(&data),
+        )?;
+        Ok(root)
+    }
+
+    // the steps are applied inside a fresh mount namespace, nothing mounted there is visible to
+    // the host and all of it goes away with the namespace. `tmp_size` (kbyte) caps the /tmp
+    // tmpfs and with `overlay` the layer taking every other write, past it they fail with ENOSPC
+    pub fn new(
+        jail_path: &str,
+        mounts: &[String],
+        binds: &[(String, String)],
+        tmp_size: Option<u64>,
+        overlay: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut jail = Jail::default();
+        // stop mount events propagating back to the host namespace
+        jail.mount(
+            None,
+            Path::new("/"),
+            None,
+            MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+            None,
+        )?;
+
+        let tmpfs_data = |mode: &str| match tmp_size {
+            Some(size) => format!("mode={},size={}k", mode, size),
+            None => format!("mode={}", mode),
+        };
+        // what the jail holds is looked up now, the overlay starts out as the same tree
+        let lower = PathBuf::from(jail_path);
+        let root = if overlay {
+            // the jail itself is never written, every run starts from the same tree
+            jail.overlay(&fs::canonicalize(jail_path)?, &tmpfs_data("755"))?
+        } else {
+            // pivot_root requires the new root to be a mount point
+            jail.bind(&lower, &lower, false)?;
+            lower.clone()
+        };
+        let root = root.as_path();
+
+        for path in mounts {
+            let source = Path::new(path);
+            if !source.exists() {
+                // e.g. /lib64 is missing on some distros
+                continue;
+            }
+            let relative = path.trim_start_matches('/');
+            let target = root.join(relative);
+            if source.is_dir() {
+                jail.mkdir_all(root, &target)?;
+            } else if !lower.join(relative).exists() {
+                if let Some(parent) = target.parent() {
+                    jail.mkdir_all(root, parent)?;
+                }
+                jail.steps.push(Step::Touch(cstring(&target)?));
+            }
+            jail.bind(source, &target, true)?;
+        }
+        // (host path, path in the jail) pairs the program may write through, e.g. fifo
+        // directories
+        for (source, target) in binds {
+            let target = root.join(target.trim_start_matches('/'));
+            jail.mkdir_all(root, &target)?;
+            jail.bind(Path::new(source), &target, false)?;
+        }
+
+        let nosuid_nodev = MsFlags::MS_NOSUID | MsFlags::MS_NODEV;
+        jail.mount_fs(
+            "proc",
+            root,
+            &root.join("proc"),
+            nosuid_nodev | MsFlags::MS_NOEXEC,
+            None,
+        )?;
+        // tmpfs pages are charged to the writer's cgroup, so /tmp counts against the memory
+        // limit too
+        jail.mount_fs(
+            "tmpfs",
+            root,
+            &root.join("tmp"),
+            nosuid_nodev,
+            Some(&tmpfs_data("1777")),
+        )?;
+        jail.mount_fs(
+            "tmpfs",
+            root,
+            &root.join("dev"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
+            Some("mode=755"),
+        )?;
+        for node in DEV_NODES {
+            let target = root.join(node.trim_start_matches('/'));
+            jail.steps.push(Step::Touch(cstring(&target)?));
+            jail.bind(Path::new(node), &target, false)?;
+        }
+
+        jail.steps.push(Step::PivotRoot(cstring(root)?));
+        Ok(jail)
+    }
+
+    // allocation free, the error carries the index of the failed step
+    pub fn apply(&self) -> Result<(), (usize, Errno)> {
+        for (i, step) in self.steps.iter().enumerate() {
+            step.apply().map_err(|e| (i, e))?;
+        }
+        Ok(())
+    }
+
+    // e.g. "mount \"proc\" on \"/jail/proc\"", for the error of a failed step
+    pub fn describe(&self, step: usize) -> String {
+        match self.steps.get(step) {
+            Some(step) => step.to_string(),
+            None => format!("step {}", step),
+        }
+    }
+}
+
+#[test]
+fn test_jail_steps() {
+    let jail = Jail::new(
+        "/nonexistent-jail",
+        &["/nonexistent-path".to_string()],
+        &[("/host/fifo".to_string(), "/fifo".to_string())],
+        Some(1024),
+        false,
+    )
+    .unwrap();
+    assert_eq!(jail.describe(2), r#"mkdir "/nonexistent-jail/fifo""#);
+    assert_eq!(
+        jail.describe(jail.steps.len() - 1),
+        r#"pivot_root into "/nonexistent-jail""#
+    );
+}
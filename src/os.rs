@@ -1,9 +1,210 @@
+use cgroups_rs::hierarchies;
+use nix::{
+    errno::Errno,
+    fcntl::{self, FcntlArg, FdFlag},
+    unistd::{self, AccessFlags, ForkResult, Pid},
+};
 use std::env;
 use std::error::Error;
+use std::fs;
+use std::io;
 use std::os::unix::fs as osfs;
+use std::os::unix::prelude::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
 
 pub fn chroot(path: &str) -> Result<(), Box<dyn Error>> {
     osfs::chroot(path)?;
     env::set_current_dir("/")?;
     Ok(())
 }
+
+// a pollable handle that becomes readable once the process exits, closed on drop
+pub struct PidFd(RawFd);
+
+impl AsRawFd for PidFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.0);
+    }
+}
+
+// linux 5.3+
+pub fn pidfd_open(pid: Pid) -> Result<PidFd, Box<dyn Error>> {
+    match unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) } {
+        -1 => Err(io::Error::last_os_error().into()),
+        fd => Ok(PidFd(fd as RawFd)),
+    }
+}
+
+// the highest fd number the process may have open, walking up to it is the fallback for
+// kernels without close_range, /proc/self/fd can't be listed without allocating
+fn fd_limit() -> RawFd {
+    match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+        limit if limit > 0 => limit.min(RawFd::MAX as libc::c_long) as RawFd,
+        _ => 1024,
+    }
+}
+
+// mark every fd from `low` up close-on-exec, so the next execve drops whatever the worker had
+// open while the setup can still use it. close_range is linux 5.11+, older kernels get each
+// fd flagged one by one. allocation free, it runs in the sandbox child
+pub fn cloexec_from(low: RawFd) -> Result<(), Errno> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_close_range,
+            low as libc::c_uint,
+            libc::c_uint::MAX,
+            libc::CLOSE_RANGE_CLOEXEC,
+        )
+    };
+    if res == 0 {
+        return Ok(());
+    }
+    for fd in low..fd_limit() {
+        match fcntl::fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)) {
+            Ok(_) | Err(Errno::EBADF) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// close every fd from `low` up except `keep`, for a process that never reaches an execve.
+// allocation free like cloexec_from
+pub fn close_from(low: RawFd, keep: RawFd) -> Result<(), Errno> {
+    let close_range = |first: RawFd, last: libc::c_uint| unsafe {
+        libc::syscall(libc::SYS_close_range, first as libc::c_uint, last, 0)
+    };
+    let res = if keep > low {
+        match close_range(low, (keep - 1) as libc::c_uint) {
+            0 => close_range(keep + 1, libc::c_uint::MAX),
+            res => res,
+        }
+    } else {
+        close_range(low.max(keep + 1), libc::c_uint::MAX)
+    };
+    if res == 0 {
+        return Ok(());
+    }
+    for fd in (low..fd_limit()).filter(|fd| *fd != keep) {
+        match unistd::close(fd) {
+            Ok(_) | Err(Errno::EBADF) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// fork(2) without glibc's wrapper, whose atfork handlers take the malloc locks. in a child of
+// a raw clone those may be held forever by a thread that only exists in the parent
+pub fn fork_raw() -> Result<ForkResult, Errno> {
+    let res = unsafe { libc::syscall(libc::SYS_clone, libc::SIGCHLD as libc::c_ulong, 0, 0, 0, 0) };
+    match Errno::result(res)? {
+        0 => Ok(ForkResult::Child),
+        pid => Ok(ForkResult::Parent {
+            child: Pid::from_raw(pid as libc::pid_t),
+        }),
+    }
+}
+
+// map `uid` inside the user namespace of `pid` to the calling user, this single mapping is all
+// an unprivileged process may write
+pub fn map_user(pid: Pid, uid: u32) -> Result<(), Box<dyn Error>> {
+    let proc_path = Path::new("/proc").join(pid.to_string());
+    fs::write(
+        proc_path.join("uid_map"),
+        format!("{} {} 1", uid, unistd::geteuid()),
+    )?;
+    fs::write(proc_path.join("setgroups"), "deny")?;
+    fs::write(
+        proc_path.join("gid_map"),
+        format!("0 {} 1", unistd::getegid()),
+    )?;
+    Ok(())
+}
+
+// the cgroup v2 the calling process is in, e.g. /sys/fs/cgroup/user.slice/worker.service
+fn own_cgroup() -> Option<PathBuf> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    Some(Path::new(CGROUP_ROOT).join(path.trim_start_matches('/')))
+}
+
+// what the rootless sandbox needs but this host does not provide, one readable line per feature.
+// any of them keeps every sandbox from starting. `pinned` means worker.cpus is set, the
+// sandbox cgroups then need the cpuset controller too
+pub fn missing_rootless_features(cgroup_parent: Option<&str>, pinned: bool) -> Vec<String> {
+    let mut missing = vec![];
+
+    let read_sysctl = |path: &str| {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    if read_sysctl("/proc/sys/user/max_user_namespaces") == Some(0)
+        || read_sysctl("/proc/sys/kernel/unprivileged_userns_clone") == Some(0)
+    {
+        missing.push(
+            "unprivileged user namespaces are disabled \
+             (user.max_user_namespaces / kernel.unprivileged_userns_clone)"
+                .to_string(),
+        );
+    }
+
+    if !hierarchies::is_cgroup2_unified_mode() {
+        missing.push(format!("cgroup v2 is not mounted at {}", CGROUP_ROOT));
+        return missing;
+    }
+    let parent = match cgroup_parent {
+        Some(parent) => Path::new(CGROUP_ROOT).join(parent.trim_start_matches('/')),
+        None => {
+            missing.push(
+                "sandbox.cgroup_parent is not set, every sandbox needs a cgroup in \
+                 a delegated cgroup v2 subtree"
+                    .to_string(),
+            );
+            return missing;
+        }
+    };
+    if unistd::access(&parent, AccessFlags::W_OK).is_err() {
+        missing.push(format!(
+            "cgroup {} is not writable by uid {}, delegate it first \
+             (e.g. systemd-run --user -p Delegate=yes)",
+            parent.display(),
+            unistd::geteuid()
+        ));
+        return missing;
+    }
+    // moving the child into its cgroup takes write access to cgroup.procs of the common
+    // ancestor of both, only the delegated subtree is writable
+    match own_cgroup() {
+        Some(own) if own.starts_with(&parent) => {}
+        own => missing.push(format!(
+            "the worker runs in cgroup {}, outside of {}, start it inside the delegated subtree",
+            own.as_deref().unwrap_or(Path::new("?")).display(),
+            parent.display()
+        )),
+    }
+    let controllers = fs::read_to_string(parent.join("cgroup.controllers")).unwrap_or_default();
+    let mut needed = vec!["memory", "pids"];
+    if pinned {
+        needed.push("cpuset");
+    }
+    for controller in needed {
+        if !controllers.split_whitespace().any(|c| c == controller) {
+            missing.push(format!(
+                "{} controller is not delegated to {}",
+                controller,
+                parent.display()
+            ));
+        }
+    }
+    missing
+}
@@ -1,61 +1,220 @@
-use ipc_channel::ipc;
 use nix::{
-    sched::{self, CloneFlags},
-    sys::resource::{self, Resource},
+    errno::Errno,
+    fcntl::{self, FcntlArg, FdFlag, OFlag},
+    poll::{self, PollFd, PollFlags},
+    sched::{self, CloneFlags, CpuSet},
+    sys::{
+        ptrace,
+        resource::{self, Resource},
+        signal::{self, Signal},
+        time::TimeSpec,
+        timerfd::{ClockId, Expiration, TimerFd, TimerFlags, TimerSetTimeFlags},
+        wait,
+    },
     unistd::{self, ForkResult, Pid, Uid},
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    env,
     error::Error,
     ffi::CString,
-    io,
+    fs::File,
+    io::{self, Read},
+    iter,
     mem::MaybeUninit,
-    os::unix::prelude::AsRawFd,
-    process, thread,
-    time::{Duration, SystemTime},
+    os::{
+        raw::c_char,
+        unix::prelude::{AsRawFd, FromRawFd, RawFd},
+    },
+    ptr,
+    time::{Duration, Instant},
 };
 use syscallz::Syscall;
+use tokio::{
+    io::{unix::AsyncFd, Interest},
+    time::{self, MissedTickBehavior},
+};
 use tracing::debug;
 
-use crate::{cgroups::Cgroup, os, seccomp};
+use crate::{
+    cgroups::Cgroup,
+    error::{ChildError, SandboxError, Stage},
+    mount, os,
+    seccomp::{self, RuleAction, SyscallRule},
+};
 
 use libc;
 
-const SIGSYS_EXIT_CODE: i32 = 159;
-const SUCCESS_EXIT_CODE: i32 = 0;
+const CHILD_STACK_SIZE: usize = 8 * 1024 * 1024;
+const SAMPLE_INTERVAL: i32 = 10; // ms, cpu usage and memory.current polling
+const SETUP_TIMEOUT: u64 = 10_000; // ms, up to execve, for runs without a real time limit
 
-#[derive(Debug)]
-pub struct RunOption<'a> {
-    pub cmd: &'a str,
+// a program getting less than 1/IDLE_CPU_RATIO of the wall time on the cpu is not making progress
+const IDLE_CPU_RATIO: u32 = 10;
+
+// what the parent observed while waiting for the child
+#[derive(Debug, Default)]
+struct WaitState {
+    timed_out: bool,
+    cpu_exceeded: bool,
+    idle: bool,
+    memory_peak: u64, // byte, highest memory.current sampled
+
+    sample_memory: bool,
+    cpu_base: u64,                // us the cgroup had used when the program started
+    cpu_limit: Option<u64>,       // us
+    idle_limit: Option<Duration>, // length of the windows cpu progress is measured over
+    idle_since: Option<(Instant, u64)>, // start of the current window and the cpu usage then
+}
+
+impl WaitState {
+    fn new(cg: &Cgroup, cpu_time_limit: Option<u64>, idle_time_limit: Option<u64>) -> Self {
+        WaitState {
+            // memory.peak is linux 5.19+, older kernels get memory.current sampled instead
+            sample_memory: cg.memory_peak().is_none(),
+            // the mounts, pivot_root and seccomp setup before execve ran in the cgroup too,
+            // WaitState is created once the program is running
+            cpu_base: cg.cpu_usage().unwrap_or(0),
+            cpu_limit: cpu_time_limit.map(|cpu_time| cpu_time * 1000),
+            idle_limit: idle_time_limit.map(Duration::from_millis),
+            ..Default::default()
+        }
+    }
+
+    // whether the child has to be looked at every SAMPLE_INTERVAL instead of only on exit
+    fn sampling(&self) -> bool {
+        self.sample_memory || self.cpu_limit.is_some() || self.idle_limit.is_some()
+    }
+
+    // true once the cgroup used up its cpu time or sat idle for a whole window
+    fn sample(&mut self, cg: &Cgroup) -> bool {
+        if self.sample_memory {
+            let current = cg.memory_current().unwrap_or(0);
+            self.memory_peak = self.memory_peak.max(current);
+        }
+        let cpu_usage = cg.cpu_usage().unwrap_or(0);
+        if let Some(cpu_limit) = self.cpu_limit {
+            if cpu_usage.saturating_sub(self.cpu_base) >= cpu_limit {
+                self.cpu_exceeded = true;
+            }
+        }
+        if let Some(idle_limit) = self.idle_limit {
+            let now = Instant::now();
+            let (start, start_usage) = *self.idle_since.get_or_insert((now, cpu_usage));
+            let wall = now.duration_since(start);
+            if wall >= idle_limit {
+                let cpu = Duration::from_micros(cpu_usage.saturating_sub(start_usage));
+                if cpu * IDLE_CPU_RATIO < wall {
+                    self.idle = true;
+                }
+                self.idle_since = Some((now, cpu_usage));
+            }
+        }
+        self.cpu_exceeded || self.idle
+    }
+}
+
+// a started sandbox, dropping it kills everything left in the cgroup, which is how an
+// abandoned run_async cleans up. a program that exited normally may still have left background
+// processes behind, the cgroup can't be removed before they are gone too
+struct Child {
+    pid: Pid,
+    cg: Cgroup,
+    status_rx: RawFd,
+    reaped: bool,
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        let _ = self.cg.kill();
+        if !self.reaped {
+            let _ = wait::waitpid(self.pid, None);
+        }
+        let _ = unistd::close(self.status_rx);
+    }
+}
 
-    pub args: Option<Vec<&'a str>>,
-    pub jail_path: Option<&'a str>,
-    pub exec_path: Option<&'a str>,
+// safety: `inner` has to own its fd, it must stay open and be the same one until the AsyncFd
+// is dropped. a bare RawFd could be closed and the number reused behind the reactor's back
+unsafe fn register<T: AsRawFd>(inner: T) -> io::Result<AsyncFd<T>> {
+    // SAFETY: upheld by the caller
+    Ok(unsafe { AsyncFd::register_with_interest(inner, Interest::READABLE) }?)
+}
+
+// an empty report means the child got through execve
+fn setup_result(report: &[u8], prepared: &Prepared) -> Result<(), SandboxError> {
+    if report.is_empty() {
+        return Ok(());
+    }
+    let e = ChildError::from_bytes(report)
+        .ok_or_else(|| SandboxError::Supervisor("malformed error from the child".to_string()))?;
+    Err(e.into_sandbox_error(|step| match &prepared.jail {
+        Some(jail) => jail.describe(step),
+        None => format!("step {}", step),
+    }))
+}
+
+// what Command::prepare works out for the child
+struct Prepared {
+    cmd: CString,
+    args: Vec<CString>,
+    env: Vec<CString>,
+    cpu_set: Option<CpuSet>,
+    exec_path: Option<CString>,
+    jail: Option<mount::Jail>,
+    seccomp: Option<seccomp::Program>,
+}
+
+// null terminated, for execve
+fn pointers(strs: &[CString]) -> Vec<*const c_char> {
+    strs.iter()
+        .map(|s| s.as_ptr())
+        .chain(iter::once(ptr::null()))
+        .collect()
+}
+
+// everything about one run, owned so it can come from the config, be sent to another process
+// or logged as json. fields missing when deserializing are left unset
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunOption {
+    pub cmd: String,
+
+    pub args: Option<Vec<String>>,
+    pub env: Option<Vec<String>>, // KEY=VALUE, the program starts with an empty environment otherwise
+    pub jail_path: Option<String>,
+    pub mounts: Option<Vec<String>>, // host paths bind-mounted read-only into the jail
+    pub binds: Option<Vec<(String, String)>>, // host directory, jail path, read-write
+    pub tmpfs_size: Option<u64>,     // kbyte, size of the jail's /tmp
+    pub overlay: bool,               // throwaway writable layer over a read-only jail
+    pub exec_path: Option<String>,
     pub uid: Option<u32>,
     pub process_limit: Option<u32>,
-    pub memory_limit: Option<u64>,    // kbyte
-    pub cpu_time_limit: Option<u64>,  // ms
-    pub real_time_limit: Option<u64>, // ms
-    pub syscall_limit: Option<&'a [Syscall]>,
-    pub stdin_redirect: Option<u32>,  // raw file descriptor
-    pub stdout_redirect: Option<u32>, // raw file descriptor
+    pub memory_limit: Option<u64>,                  // kbyte
+    pub cpu_time_limit: Option<u64>,                // ms
+    pub real_time_limit: Option<u64>,               // ms
+    pub output_limit: Option<u64>,                  // kbyte, per file written
+    pub stack_limit: Option<u64>,                   // kbyte
+    pub file_limit: Option<u64>,                    // open files
+    pub address_space_limit: Option<u64>,           // kbyte
+    pub idle_time_limit: Option<u64>,               // ms of wall time with next to no cpu progress
+    pub cpus: Option<Vec<usize>>,                   // cores the run is pinned to
+    pub syscall_limit: Option<Vec<SyscallRule>>,    // allowlist
+    pub syscall_default_action: Option<RuleAction>, // for what the allowlist misses, kill if unset
+    pub stdin_redirect: Option<u32>,                // raw file descriptor
+    pub stdout_redirect: Option<u32>,               // raw file descriptor
+    pub stderr_redirect: Option<u32>,               // raw file descriptor
+    pub keep_fds: Option<Vec<u32>>,                 // raw file descriptors left open across execve
+    pub give_fds: Option<Vec<u32>>, // raw file descriptors the parent closes once cloned
+    pub pid_namespace: bool,
+    pub rootless: bool,                // run in a user namespace, no root needed
+    pub cgroup_parent: Option<String>, // delegated cgroup v2 subtree
 }
 
-impl<'a> RunOption<'a> {
-    fn default(cmd: &'a str) -> Self {
+impl RunOption {
+    pub fn new(cmd: impl Into<String>) -> Self {
         Self {
-            cmd,
-            args: None,
-            jail_path: None,
-            exec_path: None,
-            uid: None,
-            process_limit: None,
-            memory_limit: None,
-            cpu_time_limit: None,
-            real_time_limit: None,
-            syscall_limit: None,
-            stdin_redirect: None,
-            stdout_redirect: None,
+            cmd: cmd.into(),
+            ..Default::default()
         }
     }
 }
@@ -65,7 +224,9 @@ pub enum ExecResult {
     Ok,
     CpuTimeLimitExceeded,
     RealTimeLimitExceeded,
+    IdlenessLimitExceeded, // the program stopped using the cpu, e.g. it waits for input
     MemoryLimitExceeded,
+    OutputLimitExceeded,
     SyscallLimitExceeded,
     RuntimeError,
 }
@@ -73,26 +234,38 @@ pub enum ExecResult {
 #[derive(Debug)]
 pub struct RunResult {
     pub result: ExecResult,
-    pub exit_code: u32,
+    pub exit_code: u32,      // 0 when killed by a signal
+    pub signal: Option<i32>, // the signal that terminated the program
+    pub core_dumped: bool,
     pub cpu_time: u64,
     pub real_time: u64,
     pub memory: u64,
+    pub syscall: Option<i32>, // the forbidden syscall the program was killed for
 }
 
 impl RunResult {
+    // `status` is the raw wait status of the program
     pub fn new(
         result: ExecResult,
-        exit_code: u32,
+        status: i32,
         cpu_time: u64,
         real_time: u64,
         memory: u64,
     ) -> Self {
+        let (exit_code, signal, core_dumped) = if libc::WIFSIGNALED(status) {
+            (0, Some(libc::WTERMSIG(status)), libc::WCOREDUMP(status))
+        } else {
+            (libc::WEXITSTATUS(status) as u32, None, false)
+        };
         Self {
             result,
             exit_code,
+            signal,
+            core_dumped,
             real_time,
             cpu_time,
             memory,
+            syscall: None,
         }
     }
 
@@ -100,27 +273,64 @@ impl RunResult {
         Self {
             result: ExecResult::Ok,
             exit_code: 0,
+            signal: None,
+            core_dumped: false,
             real_time: 0,
             cpu_time: 0,
             memory: 0,
+            syscall: None,
+        }
+    }
+
+    // e.g. "openat", the number if libseccomp does not know it
+    pub fn syscall_name(&self) -> Option<String> {
+        self.syscall.map(|syscall| {
+            seccomp::syscall_name(syscall).unwrap_or_else(|| format!("syscall {}", syscall))
+        })
+    }
+
+    // why the program ended abnormally, e.g. "SIGSEGV" or "exit code 3"
+    pub fn exit_reason(&self) -> Option<String> {
+        match self.signal {
+            Some(signal) => Some(match Signal::try_from(signal) {
+                Ok(signal) => signal.as_str().to_string(),
+                Err(_) => format!("signal {}", signal),
+            }),
+            None if self.exit_code != 0 => Some(format!("exit code {}", self.exit_code)),
+            None => None,
         }
     }
 }
-pub struct Command<'a> {
-    pub option: RunOption<'a>,
+pub struct Command {
+    pub option: RunOption,
     pub result: Option<RunResult>,
 }
 
-impl<'a> Command<'a> {
-    pub fn new(cmd: &'a str) -> Self {
+impl From<RunOption> for Command {
+    fn from(option: RunOption) -> Self {
         Self {
-            option: RunOption::default(cmd),
+            option,
             result: None,
         }
     }
+}
 
-    pub fn args(&mut self, args: Vec<&'a str>) -> &mut Self {
-        self.option.args = Some(args);
+fn owned(strs: impl IntoIterator<Item = impl Into<String>>) -> Vec<String> {
+    strs.into_iter().map(Into::into).collect()
+}
+
+impl Command {
+    pub fn new(cmd: impl Into<String>) -> Self {
+        Self::from(RunOption::new(cmd))
+    }
+
+    pub fn args(&mut self, args: impl IntoIterator<Item = impl Into<String>>) -> &mut Self {
+        self.option.args = Some(owned(args));
+        self
+    }
+
+    pub fn env(&mut self, env: impl IntoIterator<Item = impl Into<String>>) -> &mut Self {
+        self.option.env = Some(owned(env));
         self
     }
 
@@ -129,13 +339,37 @@ impl<'a> Command<'a> {
         self
     }
 
-    pub fn jail_path(&mut self, jail_path: &'a str) -> &mut Self {
-        self.option.jail_path = Some(jail_path);
+    pub fn jail_path(&mut self, jail_path: impl Into<String>) -> &mut Self {
+        self.option.jail_path = Some(jail_path.into());
         self
     }
 
-    pub fn exec_path(&mut self, exec_path: &'a str) -> &mut Self {
-        self.option.exec_path = Some(exec_path);
+    pub fn mounts(&mut self, mounts: impl IntoIterator<Item = impl Into<String>>) -> &mut Self {
+        self.option.mounts = Some(owned(mounts));
+        self
+    }
+
+    // bind the host directory `source` read-write at `target` inside the jail
+    pub fn bind(&mut self, source: impl Into<String>, target: impl Into<String>) -> &mut Self {
+        self.option
+            .binds
+            .get_or_insert_with(Vec::new)
+            .push((source.into(), target.into()));
+        self
+    }
+
+    pub fn tmpfs(&mut self, size: u64) -> &mut Self {
+        self.option.tmpfs_size = Some(size);
+        self
+    }
+
+    pub fn overlay(&mut self, overlay: bool) -> &mut Self {
+        self.option.overlay = overlay;
+        self
+    }
+
+    pub fn exec_path(&mut self, exec_path: impl Into<String>) -> &mut Self {
+        self.option.exec_path = Some(exec_path.into());
         self
     }
 
@@ -159,8 +393,52 @@ impl<'a> Command<'a> {
         self
     }
 
-    pub fn syscall(&mut self, syscall: &'a [Syscall]) -> &mut Self {
-        self.option.syscall_limit = Some(syscall);
+    // catches a program blocked on input or sleeping long before the real time limit would
+    pub fn idle_time(&mut self, idle_time: u64) -> &mut Self {
+        self.option.idle_time_limit = Some(idle_time);
+        self
+    }
+
+    pub fn output(&mut self, output: u64) -> &mut Self {
+        self.option.output_limit = Some(output);
+        self
+    }
+
+    pub fn stack(&mut self, stack: u64) -> &mut Self {
+        self.option.stack_limit = Some(stack);
+        self
+    }
+
+    pub fn files(&mut self, files: u64) -> &mut Self {
+        self.option.file_limit = Some(files);
+        self
+    }
+
+    // virtual memory, runtimes that reserve large heaps up front (jvm, go) fail under it
+    pub fn address_space(&mut self, address_space: u64) -> &mut Self {
+        self.option.address_space_limit = Some(address_space);
+        self
+    }
+
+    // keep the run on these cores, a judgement's timings then don't depend on what else the
+    // machine is running
+    pub fn cpus(&mut self, cpus: Vec<usize>) -> &mut Self {
+        self.option.cpus = Some(cpus);
+        self
+    }
+
+    pub fn syscall(&mut self, syscall: &[Syscall]) -> &mut Self {
+        self.option.syscall_limit = Some(syscall.iter().copied().map(SyscallRule::from).collect());
+        self
+    }
+
+    pub fn syscall_rules(&mut self, rules: Vec<SyscallRule>) -> &mut Self {
+        self.option.syscall_limit = Some(rules);
+        self
+    }
+
+    pub fn syscall_default(&mut self, action: RuleAction) -> &mut Self {
+        self.option.syscall_default_action = Some(action);
         self
     }
 
@@ -174,141 +452,698 @@ impl<'a> Command<'a> {
         self
     }
 
-    pub fn option(&mut self, option: RunOption<'a>) -> &mut Self {
+    pub fn stderr(&mut self, fd: u32) -> &mut Self {
+        self.option.stderr_redirect = Some(fd);
+        self
+    }
+
+    // every other fd above stderr is closed before execve
+    pub fn keep_fds(&mut self, fds: Vec<u32>) -> &mut Self {
+        self.option.keep_fds = Some(fds);
+        self
+    }
+
+    // hand fds over to the child, e.g. its pipe ends, the parent's copies are closed right
+    // after clone so the other end sees eof once the child is gone
+    pub fn give_fds(&mut self, fds: Vec<u32>) -> &mut Self {
+        self.option.give_fds = Some(fds);
+        self
+    }
+
+    pub fn pid_namespace(&mut self, pid_namespace: bool) -> &mut Self {
+        self.option.pid_namespace = pid_namespace;
+        self
+    }
+
+    pub fn rootless(&mut self, rootless: bool) -> &mut Self {
+        self.option.rootless = rootless;
+        self
+    }
+
+    pub fn cgroup_parent(&mut self, cgroup_parent: impl Into<String>) -> &mut Self {
+        self.option.cgroup_parent = Some(cgroup_parent.into());
+        self
+    }
+
+    pub fn option(&mut self, option: RunOption) -> &mut Self {
         self.option = option;
         self
     }
 
-    pub fn run(&mut self) -> Result<RunResult, Box<dyn Error>> {
-        let (tx_cgroup, rx_cgroup) = ipc::channel()?;
-        match unsafe { unistd::fork()? } {
-            ForkResult::Child => {
-                // new a network namspace for child process
-                sched::unshare(CloneFlags::CLONE_NEWNET)?;
+    // everything the child needs that allocates, worked out before the clone. the child
+    // shares the heap of a process with other threads whose malloc locks it may have been
+    // cloned holding, so from there on it sticks to syscalls on what is in here
+    fn prepare(&self) -> Result<Prepared, SandboxError> {
+        let cstring = |s: &str| CString::new(s).map_err(SandboxError::cause(SandboxError::Exec));
+        let cstrings = |strs: &Option<Vec<String>>| match strs {
+            Some(strs) => strs.iter().map(|s| cstring(s)).collect(),
+            None => Ok(vec![]),
+        };
+        let cmd = cstring(&self.option.cmd)?;
+        let args = cstrings(&self.option.args)?;
+        // never the worker's own environment, it holds NATS_URL and whatever dotenv loaded
+        let env = cstrings(&self.option.env)?;
 
-                unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
-                if let Some(fd) = self.option.stdin_redirect {
-                    unistd::dup2(fd as i32, io::stdin().as_raw_fd())?;
-                }
-                if let Some(fd) = self.option.stdout_redirect {
-                    unistd::dup2(fd as i32, io::stdout().as_raw_fd())?;
+        let cpu_set = match &self.option.cpus {
+            Some(cpus) => {
+                let mut cpu_set = CpuSet::new();
+                for cpu in cpus {
+                    cpu_set
+                        .set(*cpu)
+                        .map_err(SandboxError::cause(SandboxError::Setup))?;
                 }
+                Some(cpu_set)
+            }
+            None => None,
+        };
+        let exec_path = match &self.option.exec_path {
+            Some(exec_path) => Some(
+                CString::new(exec_path.as_str())
+                    .map_err(SandboxError::cause(SandboxError::Chdir))?,
+            ),
+            None => None,
+        };
+        let jail = match &self.option.jail_path {
+            Some(jail_path) => Some(
+                mount::Jail::new(
+                    jail_path,
+                    self.option.mounts.as_deref().unwrap_or_default(),
+                    self.option.binds.as_deref().unwrap_or_default(),
+                    self.option.tmpfs_size,
+                    self.option.overlay,
+                )
+                .map_err(SandboxError::cause(SandboxError::Mount))?,
+            ),
+            None => None,
+        };
+        let seccomp = match &self.option.syscall_limit {
+            Some(syscalls) => {
+                let default_action = self
+                    .option
+                    .syscall_default_action
+                    .unwrap_or(RuleAction::Kill);
+                // the init traces the program to read which syscall a SIGSYS was raised for
+                let traced = self.option.pid_namespace;
+                Some(
+                    seccomp::compile(syscalls, default_action, traced)
+                        .map_err(SandboxError::cause(SandboxError::Seccomp))?,
+                )
+            }
+            None => None,
+        };
+        Ok(Prepared {
+            cmd,
+            args,
+            env,
+            cpu_set,
+            exec_path,
+            jail,
+            seccomp,
+        })
+    }
 
-                rx_cgroup.recv().unwrap(); // wait for parent create cgroup
+    // `argv` and `envp` point into `prepared`, null terminated
+    fn exec(
+        &self,
+        prepared: &Prepared,
+        argv: &[*const c_char],
+        envp: &[*const c_char],
+    ) -> Result<(), ChildError> {
+        unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0))
+            .map_err(ChildError::at(Stage::Setup))?;
+        let redirect = |fd: u32, to: RawFd| {
+            unistd::dup2(fd as i32, to).map_err(ChildError::at(Stage::Redirect))
+        };
+        if let Some(fd) = self.option.stdin_redirect {
+            redirect(fd, libc::STDIN_FILENO)?;
+        }
+        if let Some(fd) = self.option.stdout_redirect {
+            redirect(fd, libc::STDOUT_FILENO)?;
+        }
+        if let Some(fd) = self.option.stderr_redirect {
+            redirect(fd, libc::STDERR_FILENO)?;
+        }
 
-                if let Some(jail_path) = &self.option.jail_path {
-                    os::chroot(jail_path)?;
-                }
-                if let Some(exec_path) = &self.option.exec_path {
-                    env::set_current_dir(exec_path)?;
-                }
-                if let Some(uid) = self.option.uid {
-                    unistd::setuid(Uid::from_raw(uid))?;
-                }
-                if let Some(syscalls) = self.option.syscall_limit {
-                    seccomp::syscall_limit(syscalls)?;
-                }
-                if let Some(cpu_time) = self.option.cpu_time_limit {
-                    let time = Some((cpu_time / 1000).max(1));
-                    resource::setrlimit(Resource::RLIMIT_CPU, time, time)?;
-                }
-                let args = &mut match &self.option.args {
-                    Some(args) => args
-                        .into_iter()
-                        .map(|s| CString::new(*s).unwrap())
-                        .collect::<Vec<CString>>(),
-                    None => vec![],
-                };
-                unistd::execv::<CString>(CString::new(self.option.cmd)?.as_c_str(), &args)?;
+        if let Some(cpu_set) = &prepared.cpu_set {
+            // the cgroup's cpuset already holds the child, this covers a cgroup subtree without
+            // the cpuset controller
+            sched::sched_setaffinity(Pid::from_raw(0), cpu_set)
+                .map_err(ChildError::at(Stage::Setup))?;
+        }
+
+        if let Some(jail) = &prepared.jail {
+            jail.apply().map_err(|(step, errno)| ChildError {
+                stage: Stage::Mount,
+                errno,
+                step: Some(step),
+            })?;
+        }
+        if let Some(exec_path) = &prepared.exec_path {
+            unistd::chdir(exec_path.as_c_str()).map_err(ChildError::at(Stage::Chdir))?;
+        }
+        // before setuid, which makes /proc/self/fd unreadable, and before seccomp, which may not
+        // allow fcntl or close_range
+        os::cloexec_from(3).map_err(ChildError::at(Stage::Setup))?;
+        for fd in self.option.keep_fds.iter().flatten() {
+            fcntl::fcntl(*fd as RawFd, FcntlArg::F_SETFD(FdFlag::empty()))
+                .map_err(ChildError::at(Stage::Setup))?;
+        }
+        let rlimit = |resource, limit| {
+            resource::setrlimit(resource, limit, limit).map_err(ChildError::at(Stage::Rlimit))
+        };
+        if let Some(cpu_time) = self.option.cpu_time_limit {
+            // the parent enforces the exact limit on the whole cgroup, this is only a backstop
+            // in case it falls behind, rounded up to whole seconds
+            rlimit(Resource::RLIMIT_CPU, Some(cpu_time.div_ceil(1000) + 1))?;
+        }
+        if let Some(output) = self.option.output_limit {
+            // writing past it raises SIGXFSZ
+            rlimit(Resource::RLIMIT_FSIZE, Some(output * 1024))?;
+        }
+        if let Some(stack) = self.option.stack_limit {
+            // takes effect for the stack execve sets up
+            rlimit(Resource::RLIMIT_STACK, Some(stack * 1024))?;
+        }
+        if let Some(files) = self.option.file_limit {
+            rlimit(Resource::RLIMIT_NOFILE, Some(files))?;
+        }
+        if let Some(address_space) = self.option.address_space_limit {
+            rlimit(Resource::RLIMIT_AS, Some(address_space * 1024))?;
+        }
+        // a core of a memory limit sized program is nothing but disk io
+        rlimit(Resource::RLIMIT_CORE, Some(0))?;
+        // after the rlimits, raising a hard limit above the worker's own takes privileges
+        if let Some(uid) = self.option.uid {
+            unistd::setuid(Uid::from_raw(uid)).map_err(ChildError::at(Stage::Setuid))?;
+        }
+        // last, nothing of the setup has to get past the filter but execve itself
+        if let Some(program) = &prepared.seccomp {
+            if self.option.pid_namespace {
+                ptrace::traceme().map_err(ChildError::at(Stage::Seccomp))?;
             }
-            ForkResult::Parent { child } => {
-                debug!("{}", child);
-                let cg = {
-                    let memory_limit = match self.option.memory_limit {
-                        Some(memory) => Some(memory * 1024),
-                        None => None,
-                    };
+            program.load().map_err(ChildError::at(Stage::Seccomp))?;
+        }
+        // nix's execve collects the pointers into a fresh Vec
+        unsafe { libc::execve(prepared.cmd.as_ptr(), argv.as_ptr(), envp.as_ptr()) };
+        Err(ChildError::at(Stage::Exec)(Errno::last()))
+    }
 
-                    let cg = Cgroup::new(memory_limit, self.option.process_limit);
-                    cg.add_task(child.as_raw() as u64).expect("add task failed");
-                    tx_cgroup.send(true)?;
-                    cg
-                };
-
-                if let Some(real_time) = self.option.real_time_limit {
-                    thread::spawn(move || {
-                        thread::sleep(Duration::from_millis(real_time));
-                        process::Command::new("kill")
-                            .args(["-9", "--", &format!("-{}", child.as_raw())])
-                            .output()
-                            // .spawn()
-                            .unwrap();
-                    });
+    // runs as pid 1 of the new pid namespace: fork the real program, reap every orphan
+    // until the program itself exits and hand its wait status to the parent, followed by the
+    // forbidden syscall it was killed for or -1.
+    // once init returns the kernel kills whatever is left in the namespace
+    fn init(
+        &self,
+        prepared: &Prepared,
+        argv: &[*const c_char],
+        envp: &[*const c_char],
+        status_tx: RawFd,
+    ) -> Result<(), ChildError> {
+        match os::fork_raw().map_err(ChildError::at(Stage::Init))? {
+            ForkResult::Child => self.exec(prepared, argv, envp),
+            ForkResult::Parent { child } => {
+                // the program holds its own copy of the error pipe until execve, the parent reads
+                // until both are gone. everything else the worker had open at clone time goes too,
+                // a pipe end left here would keep the other side from ever seeing EOF or EPIPE
+                os::close_from(3, status_tx).map_err(ChildError::at(Stage::Init))?;
+                let mut exec_seen = false;
+                let mut syscall = -1;
+                loop {
+                    let mut status = 0;
+                    let pid = unsafe { libc::waitpid(-1, &mut status, libc::__WALL) };
+                    if pid > 0 && libc::WIFSTOPPED(status) {
+                        // only a traced task stops here: once after the program's execve, on
+                        // every signal it gets and on the ptrace events asked for below
+                        let task = Pid::from_raw(pid);
+                        let signal = Signal::try_from(libc::WSTOPSIG(status)).ok();
+                        let res = match signal {
+                            _ if status >> 16 != 0 => ptrace::cont(task, None),
+                            Some(Signal::SIGTRAP) if !exec_seen => {
+                                exec_seen = true;
+                                // every thread and child it starts is traced as well, a task
+                                // left untraced could catch the SIGSYS itself and go on
+                                ptrace::setoptions(
+                                    task,
+                                    ptrace::Options::PTRACE_O_TRACECLONE
+                                        | ptrace::Options::PTRACE_O_TRACEFORK
+                                        | ptrace::Options::PTRACE_O_TRACEVFORK
+                                        | ptrace::Options::PTRACE_O_TRACEEXEC
+                                        | ptrace::Options::PTRACE_O_EXITKILL,
+                                )
+                                .and_then(|_| ptrace::cont(task, None))
+                            }
+                            // a newly traced task starts stopped, a program stopping itself
+                            // would only sit there until the time limit
+                            Some(Signal::SIGSTOP) => ptrace::cont(task, None),
+                            Some(Signal::SIGSYS) => match ptrace::getsiginfo(task)
+                                .ok()
+                                .as_ref()
+                                .and_then(seccomp::violation)
+                            {
+                                // not left to the program, a handler could swallow it
+                                Some(nr) => {
+                                    syscall = nr;
+                                    let _ = signal::kill(task, Signal::SIGKILL);
+                                    signal::kill(child, Signal::SIGKILL)
+                                }
+                                None => ptrace::cont(task, signal),
+                            },
+                            signal => ptrace::cont(task, signal),
+                        };
+                        match res {
+                            // killed meanwhile, the exit shows up next
+                            Ok(_) | Err(Errno::ESRCH) => continue,
+                            Err(e) => return Err(ChildError::at(Stage::Init)(e)),
+                        }
+                    }
+                    if pid == child.as_raw() {
+                        let mut report = [0u8; 8];
+                        report[..4].copy_from_slice(&status.to_ne_bytes());
+                        report[4..].copy_from_slice(&syscall.to_ne_bytes());
+                        unistd::write(status_tx, &report).map_err(ChildError::at(Stage::Init))?;
+                        return Ok(());
+                    }
+                    if pid < 0 {
+                        return Err(ChildError::at(Stage::Init)(Errno::last()));
+                    }
                 }
+            }
+        }
+    }
 
-                let now = SystemTime::now();
-                let (status, usage) = unsafe {
-                    let mut status = MaybeUninit::uninit();
-                    let mut usage = MaybeUninit::uninit();
-                    libc::wait4(child.as_raw(), status.as_mut_ptr(), 0, usage.as_mut_ptr());
-                    (status.assume_init(), usage.assume_init())
-                };
-                debug!("{:?}", usage);
-                let real_time = now.elapsed()?.as_millis() as u64;
-                let cpu_time =
-                    (usage.ru_utime.tv_sec * 1000 + usage.ru_utime.tv_usec / 1000) as u64;
-                let memory = usage.ru_maxrss as u64;
-                let mut res = ExecResult::Ok;
-
-                if status != SUCCESS_EXIT_CODE {
-                    res = ExecResult::RuntimeError;
-                }
-                if cg.oom_killed() {
-                    res = ExecResult::MemoryLimitExceeded;
-                }
-                if let Some(memory_limit) = self.option.memory_limit {
-                    if memory > memory_limit {
-                        res = ExecResult::MemoryLimitExceeded;
+    // block until the child exits without reaping it, the child's cgroup is killed once the
+    // real time limit passes or the cgroup used up its cpu time
+    fn wait_exit(&self, child: &Child) -> Result<WaitState, Box<dyn Error>> {
+        let pidfd = os::pidfd_open(child.pid)?;
+        let timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, TimerFlags::TFD_CLOEXEC)?;
+        if let Some(real_time) = self.option.real_time_limit {
+            let expiration = TimeSpec::from(Duration::from_millis(real_time));
+            timer.set(Expiration::OneShot(expiration), TimerSetTimeFlags::empty())?;
+        }
+
+        let mut state = WaitState::new(
+            &child.cg,
+            self.option.cpu_time_limit,
+            self.option.idle_time_limit,
+        );
+        let timeout = if state.sampling() {
+            SAMPLE_INTERVAL
+        } else {
+            -1
+        };
+
+        let readable = |fd: &PollFd| fd.revents().is_some_and(|r| r.contains(PollFlags::POLLIN));
+        loop {
+            if state.sample(&child.cg) {
+                child.cg.kill()?;
+                return Ok(state);
+            }
+            let mut fds = [
+                PollFd::new(pidfd.as_raw_fd(), PollFlags::POLLIN),
+                PollFd::new(timer.as_raw_fd(), PollFlags::POLLIN),
+            ];
+            match poll::poll(&mut fds, timeout) {
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+            if readable(&fds[0]) {
+                return Ok(state);
+            }
+            if readable(&fds[1]) {
+                state.timed_out = true;
+                child.cg.kill()?;
+                return Ok(state);
+            }
+        }
+    }
+
+    // the setup up to execve gets as long as the run itself, a child stuck in it (e.g. on a
+    // hung nfs mount) is killed with its cgroup once that passes
+    fn setup_timeout(&self) -> Duration {
+        Duration::from_millis(self.option.real_time_limit.unwrap_or(SETUP_TIMEOUT))
+    }
+
+    fn setup_timed_out(&self, child: &Child) -> SandboxError {
+        if let Err(e) = child.cg.kill() {
+            return SandboxError::Cgroup(e.to_string());
+        }
+        SandboxError::Setup(format!(
+            "no execve within {} ms",
+            self.setup_timeout().as_millis()
+        ))
+    }
+
+    // block until the child execs or reports why it could not, at most setup_timeout
+    fn read_setup_report(
+        &self,
+        child: &Child,
+        mut error_rx: File,
+    ) -> Result<Vec<u8>, SandboxError> {
+        fcntl::fcntl(error_rx.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        let deadline = Instant::now() + self.setup_timeout();
+        let mut report = vec![];
+        loop {
+            // read_to_end keeps what it got so far when the pipe runs dry
+            match error_rx.read_to_end(&mut report) {
+                Ok(_) => return Ok(report),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(self.setup_timed_out(child));
+            }
+            let mut fds = [PollFd::new(error_rx.as_raw_fd(), PollFlags::POLLIN)];
+            match poll::poll(&mut fds, left.as_millis().min(i32::MAX as u128) as i32) {
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // clone the child into its namespaces and cgroup and let it go, the returned pipe end
+    // reports whether it made it to execve
+    fn spawn(&self, prepared: &Prepared) -> Result<(Child, File), SandboxError> {
+        if self.option.rootless && self.option.jail_path.is_some() && !self.option.pid_namespace {
+            // proc can only be mounted by the owner of the pid namespace
+            return Err(SandboxError::Namespace(
+                "rootless jail needs a pid namespace to mount /proc".to_string(),
+            ));
+        }
+        // a byte once the parent created the cgroup and mapped the uid, eof means it gave up
+        let (ready_rx, ready_tx) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let (status_rx, status_tx) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        // the child reports why it could not get to execve here, a successful execve closes it
+        let (error_rx, error_tx) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        let error_rx = unsafe { File::from_raw_fd(error_rx) };
+        // new a network and mount namspace for child process
+        let mut flags = CloneFlags::CLONE_NEWNET | CloneFlags::CLONE_NEWNS;
+        if self.option.pid_namespace {
+            flags |= CloneFlags::CLONE_NEWPID;
+        }
+        if self.option.rootless {
+            // every other namespace is then owned by the new user namespace
+            flags |= CloneFlags::CLONE_NEWUSER;
+        }
+        let argv = pointers(&prepared.args);
+        let envp = pointers(&prepared.env);
+        let mut stack = vec![0u8; CHILD_STACK_SIZE];
+        let child = {
+            sched::clone(
+                Box::new(|| {
+                    // nothing in here may allocate, see prepare
+                    let _ = unistd::close(ready_tx);
+                    let mut ready = [0u8; 1];
+                    if unistd::read(ready_rx, &mut ready) != Ok(1) {
+                        return 1;
                     }
-                }
-                // TODO move to config file
-                let abs = 10;
-                if let Some(real_time_limit) = self.option.real_time_limit {
-                    if real_time + abs > real_time_limit {
-                        debug!("RealTimeLimitExceeded {} {}", real_time, real_time_limit);
-                        res = ExecResult::RealTimeLimitExceeded;
+                    let res = if self.option.pid_namespace {
+                        self.init(prepared, &argv, &envp, status_tx)
+                    } else {
+                        self.exec(prepared, &argv, &envp)
+                    };
+                    if let Err(e) = res {
+                        let _ = unistd::write(error_tx, &e.to_bytes());
                     }
+                    1
+                }),
+                &mut stack,
+                flags,
+                Some(libc::SIGCHLD),
+            )
+        };
+        for fd in self.option.give_fds.iter().flatten() {
+            let _ = unistd::close(*fd as RawFd);
+        }
+        let _ = unistd::close(ready_rx);
+        let child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = unistd::close(ready_tx);
+                return Err(SandboxError::Namespace(e.to_string()));
+            }
+        };
+        unistd::close(status_tx)?;
+        unistd::close(error_tx)?;
+
+        debug!("{}", child);
+        let abort = |e: SandboxError| {
+            // the child is still blocked on the pipe and holds nothing yet
+            let _ = unistd::close(ready_tx);
+            let _ = signal::kill(child, Signal::SIGKILL);
+            let _ = wait::waitpid(child, None);
+            let _ = unistd::close(status_rx);
+            e
+        };
+        let cg = {
+            let memory_limit = match self.option.memory_limit {
+                Some(memory) => Some(memory * 1024),
+                None => None,
+            };
+            // the init of the pid namespace takes one slot as well
+            let process_limit = match self.option.process_limit {
+                Some(process) if self.option.pid_namespace => Some(process + 1),
+                process => process,
+            };
+
+            let cg = Cgroup::new(
+                memory_limit,
+                process_limit,
+                self.option.cpus.as_deref(),
+                self.option.cgroup_parent.as_deref(),
+            )
+            .map_err(SandboxError::cause(SandboxError::Cgroup))
+            .map_err(abort)?;
+            cg.add_task(child.as_raw() as u64)
+                .map_err(SandboxError::cause(SandboxError::Cgroup))
+                .map_err(abort)?;
+            if self.option.rootless {
+                os::map_user(child, self.option.uid.unwrap_or(0))
+                    .map_err(SandboxError::cause(SandboxError::Namespace))
+                    .map_err(abort)?;
+            }
+            unistd::write(ready_tx, &[1])
+                .map_err(SandboxError::cause(SandboxError::Supervisor))
+                .map_err(abort)?;
+            unistd::close(ready_tx)?;
+            cg
+        };
+        Ok((
+            Child {
+                pid: child,
+                cg,
+                status_rx,
+                reaped: false,
+            },
+            error_rx,
+        ))
+    }
+
+    pub fn run(&mut self) -> Result<RunResult, SandboxError> {
+        let prepared = self.prepare()?;
+        let (child, error_rx) = self.spawn(&prepared)?;
+        let report = self.read_setup_report(&child, error_rx)?;
+        setup_result(&report, &prepared)?;
+
+        let now = Instant::now();
+        let state = self
+            .wait_exit(&child)
+            .map_err(SandboxError::cause(SandboxError::Supervisor))?;
+        self.finish(child, state, now)
+    }
+
+    // same as run but waits on the runtime instead of blocking the thread, dropping the future
+    // kills the sandbox
+    pub async fn run_async(&mut self) -> Result<RunResult, SandboxError> {
+        let prepared = self.prepare()?;
+        let (child, error_rx) = self.spawn(&prepared)?;
+        fcntl::fcntl(error_rx.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        // SAFETY: the File owns the pipe end and closes it only on drop
+        let error_rx = unsafe { register(error_rx) }?;
+        let mut report = vec![];
+        let setup = time::timeout(self.setup_timeout(), async {
+            loop {
+                let mut guard = error_rx.readable().await?;
+                // read_to_end keeps what it got so far when the pipe runs dry
+                if let Ok(res) = guard.try_io(|fd| fd.get_ref().read_to_end(&mut report)) {
+                    return res;
                 }
-                if let Some(cpu_time_limit) = self.option.cpu_time_limit {
-                    if cpu_time + abs > cpu_time_limit {
-                        debug!("CpuTimeLimitExceeded {} {}", cpu_time, cpu_time_limit);
-                        res = ExecResult::CpuTimeLimitExceeded;
-                    }
+            }
+        });
+        match setup.await {
+            Ok(res) => {
+                res?;
+            }
+            Err(_) => return Err(self.setup_timed_out(&child)),
+        }
+        setup_result(&report, &prepared)?;
+
+        let now = Instant::now();
+        let pidfd = os::pidfd_open(child.pid)
+            .map_err(SandboxError::cause(SandboxError::Supervisor))
+            // SAFETY: PidFd owns the fd and closes it only on drop
+            .and_then(|pidfd| Ok(unsafe { register(pidfd) }?))?;
+        let deadline = time::sleep(Duration::from_millis(
+            self.option.real_time_limit.unwrap_or(u64::MAX),
+        ));
+        tokio::pin!(deadline);
+        let mut interval = time::interval(Duration::from_millis(SAMPLE_INTERVAL as u64));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut state = WaitState::new(
+            &child.cg,
+            self.option.cpu_time_limit,
+            self.option.idle_time_limit,
+        );
+        loop {
+            if state.sample(&child.cg) {
+                break;
+            }
+            tokio::select! {
+                res = pidfd.readable() => {
+                    drop(res?);
+                    break;
                 }
-                if let Some(_) = self.option.syscall_limit {
-                    if status == SIGSYS_EXIT_CODE {
-                        res = ExecResult::SyscallLimitExceeded;
-                    }
+                _ = &mut deadline, if self.option.real_time_limit.is_some() => {
+                    state.timed_out = true;
+                    break;
                 }
-                return Ok(RunResult::new(
-                    res,
-                    status as u32,
-                    cpu_time,
-                    real_time,
-                    memory,
-                ));
+                _ = interval.tick(), if state.sampling() => {}
             }
         }
+        if state.timed_out || state.cpu_exceeded || state.idle {
+            child
+                .cg
+                .kill()
+                .map_err(SandboxError::cause(SandboxError::Cgroup))?;
+            // wait for the kill to land so finish does not block in wait4
+            drop(pidfd.readable().await?);
+        }
+        self.finish(child, state, now)
+    }
 
-        Err("gg".into())
+    // reap the exited child and work out the verdict
+    fn finish(
+        &self,
+        mut child: Child,
+        state: WaitState,
+        now: Instant,
+    ) -> Result<RunResult, SandboxError> {
+        let (status, usage) = unsafe {
+            let mut status = MaybeUninit::uninit();
+            let mut usage = MaybeUninit::uninit();
+            libc::wait4(
+                child.pid.as_raw(),
+                status.as_mut_ptr(),
+                0,
+                usage.as_mut_ptr(),
+            );
+            (status.assume_init(), usage.assume_init())
+        };
+        child.reaped = true;
+        debug!("{:?}", usage);
+        // with a pid namespace we waited for the init, the program's own status comes from the pipe
+        let (status, syscall) = {
+            let mut buf = [0u8; 8];
+            match unistd::read(child.status_rx, &mut buf) {
+                Ok(8) => {
+                    let (status, syscall) = buf.split_at(4);
+                    let syscall = i32::from_ne_bytes(syscall.try_into().unwrap());
+                    (
+                        i32::from_ne_bytes(status.try_into().unwrap()),
+                        (syscall >= 0).then_some(syscall),
+                    )
+                }
+                _ => (status, None),
+            }
+        };
+        let real_time = now.elapsed().as_millis() as u64;
+        // user + system time of every task that ran in the cgroup since the program started
+        let cpu_time = match child.cg.cpu_usage() {
+            Some(cpu_usage) => cpu_usage.saturating_sub(state.cpu_base) / 1000,
+            None => {
+                let ms = |t: libc::timeval| (t.tv_sec * 1000 + t.tv_usec / 1000) as u64;
+                ms(usage.ru_utime) + ms(usage.ru_stime)
+            }
+        };
+        // ru_maxrss only knows the largest single process, the cgroup charge is what
+        // the memory limit is enforced on
+        let memory = child.cg.memory_peak().unwrap_or(state.memory_peak) / 1024;
+        let mut run_result = RunResult::new(ExecResult::Ok, status, cpu_time, real_time, memory);
+        run_result.syscall = syscall;
+        let signal = run_result.signal;
+
+        let mut res = ExecResult::Ok;
+        if signal.is_some() || run_result.exit_code != 0 {
+            res = ExecResult::RuntimeError;
+        }
+        if self.option.output_limit.is_some() && signal == Some(libc::SIGXFSZ) {
+            res = ExecResult::OutputLimitExceeded;
+        }
+        if child.cg.oom_killed() {
+            res = ExecResult::MemoryLimitExceeded;
+        }
+        if let Some(memory_limit) = self.option.memory_limit {
+            if memory > memory_limit {
+                res = ExecResult::MemoryLimitExceeded;
+            }
+        }
+        // TODO move to config file
+        let abs = 10;
+        if let Some(real_time_limit) = self.option.real_time_limit {
+            if state.timed_out || real_time + abs > real_time_limit {
+                debug!("RealTimeLimitExceeded {} {}", real_time, real_time_limit);
+                res = ExecResult::RealTimeLimitExceeded;
+            }
+        }
+        if state.idle {
+            debug!("IdlenessLimitExceeded {} {}", cpu_time, real_time);
+            res = ExecResult::IdlenessLimitExceeded;
+        }
+        if let Some(cpu_time_limit) = self.option.cpu_time_limit {
+            // SIGXCPU comes from the RLIMIT_CPU backstop
+            if state.cpu_exceeded
+                || signal == Some(libc::SIGXCPU)
+                || cpu_time + abs > cpu_time_limit
+            {
+                debug!("CpuTimeLimitExceeded {} {}", cpu_time, cpu_time_limit);
+                res = ExecResult::CpuTimeLimitExceeded;
+            }
+        }
+        if self.option.syscall_limit.is_some()
+            && (syscall.is_some() || signal == Some(libc::SIGSYS))
+        {
+            res = ExecResult::SyscallLimitExceeded;
+        }
+        run_result.result = res;
+        Ok(run_result)
     }
 }
 
+#[test]
+fn test_run_option_serde() {
+    let option: RunOption = toml::from_str(
+        r#"
+        cmd = "/usr/bin/java"
+        args = ["java", "Main"]
+        process_limit = 32
+        syscall_limit = ["read", "write"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(option.process_limit, Some(32));
+    assert_eq!(option.memory_limit, None);
+
+    let sent: RunOption = serde_json::from_slice(&serde_json::to_vec(&option).unwrap()).unwrap();
+    assert_eq!(sent.cmd, "/usr/bin/java");
+    assert_eq!(sent.args, option.args);
+    assert_eq!(sent.syscall_limit, option.syscall_limit);
+}
+
 #[test]
 #[allow(unused_imports)]
 pub fn test_run_command() {
-    use crate::languages::Cpp;
-    use crate::languages::Language;
     use std::fs::OpenOptions;
 
     // let syscalls = Cpp::allowed_syscalls();
@@ -1,13 +1,400 @@
-use std::error::Error;
+use nix::errno::Errno;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    ffi::CStr,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    mem,
+    os::{
+        raw::{c_char, c_int},
+        unix::prelude::FromRawFd,
+    },
+};
 
-use syscallz::{Context, Syscall};
+use seccomp_sys::{
+    scmp_arg_cmp, scmp_compare, scmp_filter_ctx, seccomp_export_bpf, seccomp_init, seccomp_release,
+    seccomp_rule_add_array, SCMP_ACT_ALLOW, SCMP_ACT_ERRNO, SCMP_ACT_TRAP,
+};
+use syscallz::Syscall;
 
-pub fn syscall_limit<'a>(allowed_syscalls: &'a [Syscall]) -> Result<(), Box<dyn Error>> {
-    let mut ctx = Context::init()?;
-    for syscall in allowed_syscalls.iter() {
-        ctx.allow_syscall(*syscall)?;
+extern "C" {
+    // libseccomp, newer than what seccomp-sys covers
+    fn seccomp_syscall_resolve_num_arch(arch_token: u32, num: c_int) -> *mut c_char;
+}
+
+// allow and write an audit record, linux 4.14+
+const SCMP_ACT_LOG: u32 = 0x7ffc0000;
+// every thread of the process dies of SIGSYS, linux 4.14+
+const SCMP_ACT_KILL_PROCESS: u32 = 0x80000000;
+
+// what the kernel puts in the siginfo of a SIGSYS raised by a filter, libc has no accessors
+#[repr(C)]
+struct SigsysInfo {
+    si_signo: c_int,
+    _si_errno: c_int,
+    si_code: c_int,
+    _call_addr: *mut libc::c_void,
+    syscall: c_int,
+    _arch: u32,
+}
+
+const SYS_SECCOMP: c_int = 1;
+
+// linux/filter.h and linux/seccomp.h
+#[repr(C)]
+struct SockFilter {
+    code: u16,
+    jt: u8,
+    jf: u8,
+    k: u32,
+}
+
+#[repr(C)]
+struct SockFprog {
+    len: u16,
+    filter: *const SockFilter,
+}
+
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+const SYSCALL_ARGS: u32 = 6;
+
+// what a matching rule, or the filter for calls no rule matches, does with the call, e.g.
+// "kill" or { errno = 1 }
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    #[default]
+    Allow,
+    // the call fails with this errno and the program goes on
+    Errno(u16),
+    // allowed, but the kernel audit log records it, for working out what a runtime needs
+    Log,
+    // the program is killed and the syscall reported
+    Kill,
+}
+
+impl RuleAction {
+    // `traced` means a tracer sees every thread of the program and kills it on the SIGSYS,
+    // without one the kernel has to kill it, a trap could be caught by a signal handler
+    fn scmp_action(self, traced: bool) -> u32 {
+        match self {
+            RuleAction::Allow => SCMP_ACT_ALLOW,
+            RuleAction::Errno(errno) => SCMP_ACT_ERRNO(errno.into()),
+            RuleAction::Log => SCMP_ACT_LOG,
+            // see Command::init
+            RuleAction::Kill if traced => SCMP_ACT_TRAP,
+            RuleAction::Kill => SCMP_ACT_KILL_PROCESS,
+        }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    MaskedEq, // arg & mask == value
+}
+
+// a condition on one of the raw syscall arguments, pointers can't be looked into
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArgRule {
+    pub arg: u32, // 0 is the first argument
+    pub op: ArgOp,
+    pub value: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<u64>, // masked_eq only
+}
+
+impl ArgRule {
+    fn comparator(&self) -> Result<scmp_arg_cmp, String> {
+        if self.arg >= SYSCALL_ARGS {
+            return Err(format!("syscalls have no argument {}", self.arg));
+        }
+        let op = match self.op {
+            ArgOp::Eq => scmp_compare::SCMP_CMP_EQ,
+            ArgOp::Ne => scmp_compare::SCMP_CMP_NE,
+            ArgOp::Lt => scmp_compare::SCMP_CMP_LT,
+            ArgOp::Le => scmp_compare::SCMP_CMP_LE,
+            ArgOp::Gt => scmp_compare::SCMP_CMP_GT,
+            ArgOp::Ge => scmp_compare::SCMP_CMP_GE,
+            ArgOp::MaskedEq => scmp_compare::SCMP_CMP_MASKED_EQ,
+        };
+        // masked_eq compares arg & datum_a against datum_b
+        let (datum_a, datum_b) = match self.op {
+            ArgOp::MaskedEq => (self.mask.ok_or("masked_eq needs a mask")?, self.value),
+            _ => (self.value, 0),
+        };
+        Ok(scmp_arg_cmp {
+            arg: self.arg,
+            op,
+            datum_a,
+            datum_b,
+        })
+    }
+}
+
+// one entry of an allowlist, either just the syscall name or e.g.
+//   { name = "socket", args = [{ arg = 0, op = "eq", value = 1 }] }
+// `action` applies when all of `args` match, several rules for one syscall are or-ed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RuleDef")]
+pub struct SyscallRule {
+    pub name: String,
+    pub action: RuleAction,
+    pub args: Vec<ArgRule>,
+}
+
+impl SyscallRule {
+    pub fn allow(name: impl Into<String>) -> Self {
+        SyscallRule {
+            name: name.into(),
+            action: RuleAction::Allow,
+            args: vec![],
+        }
+    }
+
+    // the syscall number and the argument conditions, fails on what libseccomp would reject
+    pub fn resolve(&self) -> Result<(Syscall, Vec<scmp_arg_cmp>), String> {
+        let syscall = Syscall::from_name(&self.name)
+            .ok_or_else(|| format!("unknown syscall {:?}", self.name))?;
+        let comparators = self
+            .args
+            .iter()
+            .map(ArgRule::comparator)
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("rule for {}: {}", self.name, e))?;
+        Ok((syscall, comparators))
+    }
+}
+
+impl From<Syscall> for SyscallRule {
+    fn from(syscall: Syscall) -> Self {
+        // the variants are named after the syscalls
+        SyscallRule::allow(format!("{:?}", syscall))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RuleDef {
+    Name(String),
+    Rule {
+        name: String,
+        #[serde(default)]
+        action: RuleAction,
+        #[serde(default)]
+        args: Vec<ArgRule>,
+    },
+}
 
-    ctx.load()?;
-    Ok(())
+impl From<RuleDef> for SyscallRule {
+    fn from(def: RuleDef) -> Self {
+        match def {
+            RuleDef::Name(name) => SyscallRule::allow(name),
+            RuleDef::Rule { name, action, args } => SyscallRule { name, action, args },
+        }
+    }
+}
+
+// a libseccomp filter context, released on drop
+struct Filter(*mut scmp_filter_ctx);
+
+impl Drop for Filter {
+    fn drop(&mut self) {
+        unsafe { seccomp_release(self.0) };
+    }
+}
+
+// the filter for the rules, calls none of them matches get `default_action`
+fn build(
+    rules: &[SyscallRule],
+    default_action: RuleAction,
+    traced: bool,
+) -> Result<Filter, Box<dyn Error>> {
+    let filter = Filter(unsafe { seccomp_init(default_action.scmp_action(traced)) });
+    if filter.0.is_null() {
+        return Err("seccomp_init failed".into());
+    }
+    for rule in rules.iter() {
+        let (syscall, comparators) = rule.resolve()?;
+        if rule.action == default_action {
+            // libseccomp refuses rules that change nothing
+            continue;
+        }
+        let ret = unsafe {
+            seccomp_rule_add_array(
+                filter.0,
+                rule.action.scmp_action(traced),
+                syscall.into_i32(),
+                comparators.len() as u32,
+                comparators.as_ptr(),
+            )
+        };
+        if ret != 0 {
+            return Err(format!(
+                "add rule for {}: {}",
+                rule.name,
+                io::Error::from_raw_os_error(-ret)
+            )
+            .into());
+        }
+    }
+    Ok(filter)
+}
+
+// build the filter without loading it, so whatever libseccomp rejects shows up before a run.
+// it silently keeps one of two rules that match the same calls with different actions, e.g. a
+// profile taking back a syscall its parent allows, so those are turned down here
+pub fn check(rules: &[SyscallRule], default_action: RuleAction) -> Result<(), Box<dyn Error>> {
+    for (i, rule) in rules.iter().enumerate() {
+        let conflict = rules[..i].iter().any(|earlier| {
+            earlier.name == rule.name && earlier.args == rule.args && earlier.action != rule.action
+        });
+        if conflict {
+            return Err(format!("conflicting rules for {}", rule.name).into());
+        }
+    }
+    build(rules, default_action, true).map(|_| ())
+}
+
+// a compiled filter, built before the sandbox child is cloned and loaded by it with nothing
+// but two syscalls
+pub struct Program(Vec<SockFilter>);
+
+// compile the rules, calls none of them matches get `default_action`
+pub fn compile(
+    rules: &[SyscallRule],
+    default_action: RuleAction,
+    traced: bool,
+) -> Result<Program, Box<dyn Error>> {
+    let filter = build(rules, default_action, traced)?;
+    let fd = unsafe { libc::memfd_create(c"seccomp".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let mut bpf = unsafe { File::from_raw_fd(fd) };
+    let ret = unsafe { seccomp_export_bpf(filter.0, fd) };
+    if ret != 0 {
+        return Err(format!("seccomp_export_bpf: {}", io::Error::from_raw_os_error(-ret)).into());
+    }
+    let mut buf = vec![];
+    bpf.seek(SeekFrom::Start(0))?;
+    bpf.read_to_end(&mut buf)?;
+    let program = buf
+        .chunks_exact(mem::size_of::<SockFilter>())
+        .map(|insn| SockFilter {
+            code: u16::from_ne_bytes([insn[0], insn[1]]),
+            jt: insn[2],
+            jf: insn[3],
+            k: u32::from_ne_bytes([insn[4], insn[5], insn[6], insn[7]]),
+        })
+        .collect();
+    Ok(Program(program))
+}
+
+impl Program {
+    // what seccomp_load does: no_new_privs, without which an unprivileged process may not
+    // install a filter, then the filter itself. allocation free
+    pub fn load(&self) -> Result<(), Errno> {
+        Errno::result(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+        let prog = SockFprog {
+            len: self.0.len() as u16,
+            filter: self.0.as_ptr(),
+        };
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                0,
+                &prog as *const SockFprog,
+            )
+        };
+        Errno::result(res).map(drop)
+    }
+}
+
+// the syscall a SIGSYS was raised for, None if it did not come from a filter
+pub fn violation(info: &libc::siginfo_t) -> Option<i32> {
+    let info = unsafe { &*(info as *const libc::siginfo_t as *const SigsysInfo) };
+    if info.si_signo != libc::SIGSYS || info.si_code != SYS_SECCOMP {
+        return None;
+    }
+    Some(info.syscall)
+}
+
+// e.g. "openat" for 257 on x86_64
+pub fn syscall_name(syscall: i32) -> Option<String> {
+    // 0 is the native architecture, the only one the filter lets through to the trap
+    let name = unsafe { seccomp_syscall_resolve_num_arch(0, syscall) };
+    if name.is_null() {
+        return None;
+    }
+    let owned = unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned();
+    unsafe { libc::free(name as *mut libc::c_void) };
+    Some(owned)
+}
+
+#[test]
+fn test_syscall_rules() {
+    #[derive(Deserialize)]
+    struct Profile {
+        syscalls: Vec<SyscallRule>,
+    }
+    let profile: Profile = toml::from_str(
+        r#"
+        syscalls = [
+            "read",
+            { name = "socket", args = [{ arg = 0, op = "eq", value = 1 }] },
+            { name = "clone", args = [{ arg = 0, op = "masked_eq", mask = 0x10000000, value = 0 }] },
+            { name = "clone3", action = { errno = 38 } },
+            { name = "ptrace", action = "kill" },
+        ]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(profile.syscalls[0], SyscallRule::allow("read"));
+    assert_eq!(profile.syscalls[1].args[0].op, ArgOp::Eq);
+    assert!(profile.syscalls[2].args[0].comparator().is_ok());
+    assert_eq!(profile.syscalls[3].action, RuleAction::Errno(38));
+    assert_eq!(profile.syscalls[4].action, RuleAction::Kill);
+
+    let missing_mask = ArgRule {
+        arg: 0,
+        op: ArgOp::MaskedEq,
+        value: 0,
+        mask: None,
+    };
+    assert!(missing_mask.comparator().is_err());
+
+    // read-only opens, the access mode bits alone still let O_TRUNC and O_CREAT write
+    let read_only: Profile = toml::from_str(
+        r#"
+        syscalls = [{ name = "openat", args = [{ arg = 2, op = "masked_eq", mask = 0x400643, value = 0 }] }]
+        "#,
+    )
+    .unwrap();
+    let cmp = read_only.syscalls[0].args[0].comparator().unwrap();
+    let allowed = |flags: i32| flags as u64 & cmp.datum_a == cmp.datum_b;
+    assert!(allowed(libc::O_RDONLY | libc::O_CLOEXEC));
+    assert!(allowed(
+        libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NONBLOCK
+    ));
+    for flags in [
+        libc::O_WRONLY,
+        libc::O_RDWR,
+        libc::O_RDONLY | libc::O_TRUNC,
+        libc::O_RDONLY | libc::O_CREAT,
+        libc::O_RDONLY | libc::O_APPEND,
+        libc::O_RDONLY | libc::O_TMPFILE,
+    ] {
+        assert!(!allowed(flags), "{:#x} got through", flags);
+    }
 }
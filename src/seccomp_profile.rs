@@ -0,0 +1,147 @@
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::{
+    config,
+    seccomp::{self, RuleAction, SyscallRule},
+};
+
+static PROFILES: OnceCell<HashMap<String, Profile>> = OnceCell::new();
+
+// a [seccomp_profile.<name>] table of the config
+#[derive(Debug, Deserialize)]
+pub struct ProfileDef {
+    #[serde(default)]
+    inherits: Option<String>,
+    #[serde(default)]
+    default_action: Option<RuleAction>, // the parent's, kill at the root
+    #[serde(default)]
+    syscalls: Vec<SyscallRule>,
+}
+
+// a profile with everything it inherits folded in, ready for Command::syscall_rules
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub default_action: RuleAction,
+    pub syscalls: Vec<SyscallRule>,
+}
+
+fn resolve(defs: &HashMap<String, ProfileDef>, name: &str) -> Result<Profile, String> {
+    let mut chain: Vec<(&str, &ProfileDef)> = vec![];
+    let mut next = Some(name);
+    while let Some(current) = next {
+        if chain.iter().any(|(seen, _)| *seen == current) {
+            return Err(format!("seccomp profile {} inherits itself", current));
+        }
+        let def = defs
+            .get(current)
+            .ok_or_else(|| format!("unknown seccomp profile {}", current))?;
+        chain.push((current, def));
+        next = def.inherits.as_deref();
+    }
+
+    let default_action = chain
+        .iter()
+        .find_map(|(_, def)| def.default_action)
+        .unwrap_or(RuleAction::Kill);
+    // the root's rules first
+    let syscalls: Vec<SyscallRule> = chain
+        .iter()
+        .rev()
+        .flat_map(|(_, def)| def.syscalls.iter().cloned())
+        .collect();
+    seccomp::check(&syscalls, default_action)
+        .map_err(|e| format!("seccomp profile {}: {}", name, e))?;
+    // the filter is in place before execve starts the program
+    let runs = |action| matches!(action, RuleAction::Allow | RuleAction::Log);
+    let execve = runs(default_action)
+        || syscalls
+            .iter()
+            .any(|rule| rule.name == "execve" && rule.args.is_empty() && runs(rule.action));
+    if !execve {
+        return Err(format!("seccomp profile {} does not allow execve", name));
+    }
+    Ok(Profile {
+        default_action,
+        syscalls,
+    })
+}
+
+pub fn parse(defs: &HashMap<String, ProfileDef>) -> Result<HashMap<String, Profile>, String> {
+    defs.keys()
+        .map(|name| Ok((name.clone(), resolve(defs, name)?)))
+        .collect()
+}
+
+// read and check every profile once, main calls it at startup so a broken one stops the worker
+pub fn load() -> Result<&'static HashMap<String, Profile>, String> {
+    PROFILES.get_or_try_init(|| {
+        let defs = match config::get_config().get("seccomp_profile") {
+            Ok(defs) => defs,
+            Err(::config::ConfigError::NotFound(_)) => HashMap::new(),
+            Err(e) => return Err(format!("seccomp_profile: {}", e)),
+        };
+        parse(&defs)
+    })
+}
+
+pub fn get(name: &str) -> Result<&'static Profile, String> {
+    load()?
+        .get(name)
+        .ok_or_else(|| format!("unknown seccomp profile {}", name))
+}
+
+#[test]
+fn test_profile_inheritance() {
+    let defs: HashMap<String, ProfileDef> = toml::from_str(
+        r#"
+        [base]
+        syscalls = ["execve", "read", "write", "exit_group"]
+        [java]
+        inherits = "base"
+        default_action = { errno = 1 }
+        syscalls = [{ name = "socket", args = [{ arg = 0, op = "eq", value = 1 }] }]
+        [java_audit]
+        inherits = "java"
+        default_action = "log"
+        "#,
+    )
+    .unwrap();
+    let profiles = parse(&defs).unwrap();
+    assert_eq!(profiles["base"].default_action, RuleAction::Kill);
+    assert_eq!(profiles["java"].default_action, RuleAction::Errno(1));
+    assert_eq!(profiles["java_audit"].default_action, RuleAction::Log);
+    let names: Vec<_> = profiles["java_audit"]
+        .syscalls
+        .iter()
+        .map(|rule| rule.name.as_str())
+        .collect();
+    assert_eq!(names, ["execve", "read", "write", "exit_group", "socket"]);
+
+    let broken: HashMap<String, ProfileDef> = toml::from_str(
+        r#"
+        [a]
+        inherits = "b"
+        syscalls = ["execve"]
+        [b]
+        inherits = "a"
+        [typo]
+        syscalls = ["execve", "raed"]
+        [no_exec]
+        syscalls = ["read"]
+        [base]
+        syscalls = ["execve", "read"]
+        [conflict]
+        inherits = "base"
+        syscalls = [{ name = "read", action = "kill" }]
+        "#,
+    )
+    .unwrap();
+    assert!(resolve(&broken, "a").is_err());
+    assert!(resolve(&broken, "typo").is_err());
+    assert!(resolve(&broken, "no_exec").is_err());
+    assert!(resolve(&broken, "missing").is_err());
+    assert!(resolve(&broken, "base").is_ok());
+    assert!(resolve(&broken, "conflict").is_err());
+}
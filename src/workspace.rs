@@ -0,0 +1,95 @@
+use std::{error::Error, fs, path::Path};
+
+const WORK_DIR: &str = "work";
+
+// one directory per judgement under <jail_path>/work, removed again on drop:
+//   box/          source and binary, the compiler's cwd and the root of every test run
+//   helper/       root of the problem's interactor or communication manager
+//   fifo/         the fifos of a communication problem, one directory per endpoint
+//   compile.txt   compiler stdout + stderr
+//   output/       <test>.out and <test>.err of each test
+// concurrent judgements never share a file, and a test run sees nothing but its own box
+pub struct Workspace {
+    root: String,
+}
+
+impl Workspace {
+    pub fn new(jail_path: &str, submission_id: &str) -> Result<Self, Box<dyn Error>> {
+        let work = Path::new(jail_path).join(WORK_DIR);
+        fs::create_dir_all(&work)?;
+
+        // the id comes from the request, keep it out of the path syntax
+        let id: String = submission_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        let root = work.join(format!("{}-{:08x}", id, rand::random::<u32>()));
+        // create_dir fails on an existing directory, so no two judgements end up in the same one
+        fs::create_dir(&root)?;
+        let root = root
+            .into_os_string()
+            .into_string()
+            .map_err(|_| "jail_path is not valid utf-8")?;
+        let workspace = Workspace { root };
+        fs::create_dir(workspace.box_path())?;
+        fs::create_dir(workspace.helper_box_path())?;
+        fs::create_dir(format!("{}/output", workspace.root))?;
+        Ok(workspace)
+    }
+
+    pub fn box_path(&self) -> String {
+        format!("{}/box", self.root)
+    }
+
+    pub fn helper_box_path(&self) -> String {
+        format!("{}/helper", self.root)
+    }
+
+    pub fn fifo_path(&self) -> String {
+        format!("{}/fifo", self.root)
+    }
+
+    pub fn src(&self, src_file_name: &str) -> String {
+        format!("{}/{}", self.box_path(), src_file_name)
+    }
+
+    pub fn compile_output(&self) -> String {
+        format!("{}/compile.txt", self.root)
+    }
+
+    pub fn output(&self, test: &str) -> String {
+        format!("{}/output/{}.out", self.root, test)
+    }
+
+    pub fn error(&self, test: &str) -> String {
+        format!("{}/output/{}.err", self.root, test)
+    }
+
+    pub fn helper_error(&self, test: &str) -> String {
+        format!("{}/output/{}.helper.err", self.root, test)
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn test_workspace_cleanup() {
+    let jail = std::env::temp_dir().join(format!("oj-workspace-{}", std::process::id()));
+    let jail = jail.to_str().unwrap();
+    let a = Workspace::new(jail, "../1").unwrap();
+    let b = Workspace::new(jail, "../1").unwrap();
+    assert_ne!(a.box_path(), b.box_path());
+    assert!(a.box_path().starts_with(&format!("{}/work/1-", jail)));
+
+    fs::write(a.output("1"), "42").unwrap();
+    let root = a.root.clone();
+    drop(a);
+    assert!(!Path::new(&root).exists());
+    assert!(Path::new(&b.box_path()).exists());
+    drop(b);
+    fs::remove_dir_all(jail).unwrap();
+}